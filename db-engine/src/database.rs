@@ -1,39 +1,148 @@
 pub mod structures;
 
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs::OpenOptions;
 use std::fs::{self, File};
 use std::io::{self, Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use structures::*;
 
 const DESCRIPTION_FILE_NAME: &str = "descriptor";
 const FREE_SPACE_FILE_NAME: &str = "free_space";
+const PRIMARY_KEY_FILE_NAME: &str = "primary_key";
 const PAGES_DIRECTORY_NAME: &str = "pages";
 const NUMBER_OF_PAGES_FILE_NAME: &str = "number";
 const INDEXES_DIRECTORY_NAME: &str = "indexes";
+const OVERFLOW_DIRECTORY_NAME: &str = "overflow";
+const SEQUENCES_DIRECTORY_NAME: &str = "sequences";
+const UNIQUE_DIRECTORY_NAME: &str = "unique";
 const PAGE_SIZE_BYTES: u16 = 4096;
 
+/// Sequences may be used by several requests at the same time, so their values are taken
+/// under this lock
+static SEQUENCES_LOCK: Mutex<()> = Mutex::new(());
+
+/// Overflow pages are numbered by a counter shared by all requests to the table, so ids
+/// are taken and the counter is written back under this lock
+static OVERFLOW_LOCK: Mutex<()> = Mutex::new(());
+
+/// Index of unique keys made of values of one or several fields
+type KeyIndex = BTreeMap<Vec<Type>, DataPosition>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// Records break a constraint of the table: name of the constraint and the details
+    ConstraintViolation(String, String),
+    Other(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ConstraintViolation(name, details) => {
+                write!(f, "Constraint '{}' is violated: {}", name, details)
+            }
+            Error::Other(details) => write!(f, "{}", details),
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(value: String) -> Self {
+        Error::Other(value)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(value: &str) -> Self {
+        Error::Other(value.to_string())
+    }
+}
+
+/// Primary key or a unique constraint together with the file of its key index
+struct KeyConstraint {
+    name: String,
+    fields: Vec<u16>,
+    /// Collations of the fields, strings are kept in the index in the folded form
+    collations: Vec<Collation>,
+    index_path: PathBuf,
+}
+
+impl KeyConstraint {
+    fn new(name: String, fields: Vec<u16>, metadata: &TableMetadata, index_path: PathBuf) -> Self {
+        let collations = fields
+            .iter()
+            .map(|&idx| metadata.fields()[idx as usize].collation)
+            .collect();
+        KeyConstraint {
+            name,
+            fields,
+            collations,
+            index_path,
+        }
+    }
+
+    /// Key of the record in the index, None if the record has null in any of the fields
+    fn key(&self, record: &[Type]) -> Option<Vec<Type>> {
+        self.key_of(self.fields.iter().map(|&idx| &record[idx as usize]))
+    }
+
+    /// Key made of the values of the fields in their order
+    fn key_of<'a>(&self, values: impl Iterator<Item = &'a Type>) -> Option<Vec<Type>> {
+        let key: Vec<Type> = values
+            .zip(&self.collations)
+            .map(|(value, collation)| collation.key(value))
+            .collect();
+        match key.contains(&Type::Null) {
+            true => None,
+            false => Some(key),
+        }
+    }
+
+    fn uses_any(&self, fields: &[usize]) -> bool {
+        self.fields
+            .iter()
+            .any(|&field| fields.contains(&(field as usize)))
+    }
+}
+
+/// Records of a table that reference deleted records through its foreign key
+struct Reference {
+    table_path: PathBuf,
+    foreign_key: ForeignKey,
+    positions: Vec<DataPosition>,
+}
+
+/// New values of records at the positions, checked but not written yet
+struct RecordChanges {
+    positions: Vec<DataPosition>,
+    old_records: Vec<Vec<Type>>,
+    new_records: Vec<Vec<Type>>,
+    /// Changed fields together with generated fields computed from them
+    changed_fields: Vec<usize>,
+}
+
 pub fn get_table_list(database_path: &Path) -> Result<Vec<String>, String> {
     let database_directory = match fs::read_dir(database_path) {
         Ok(dir) => dir,
-        Err(e) => {
+        Err(_) => {
             return Ok(Vec::new());
         }
     };
     let mut tables: Vec<String> = Vec::new();
-    for entry in database_directory {
-        if let Ok(entry) = entry {
-            let path = entry.path();
-            if path.is_dir() && dir_is_table(&path) {
-                let dir_name = match entry.file_name().into_string() {
-                    Ok(name) => name,
-                    Err(_) => {
-                        return Err(String::from("Couldn't convers from OsString to String."));
-                    }
-                };
-                tables.push(dir_name);
-            }
+    for entry in database_directory.flatten() {
+        let path = entry.path();
+        if path.is_dir() && dir_is_table(&path) {
+            let dir_name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => {
+                    return Err(String::from("Couldn't convers from OsString to String."));
+                }
+            };
+            tables.push(dir_name);
         }
     }
     Ok(tables)
@@ -44,6 +153,7 @@ pub fn create_table(
     name: &str,
     metadata: TableMetadata,
 ) -> Result<(), String> {
+    check_foreign_keys(database_path, name, &metadata)?;
     let table_directory = database_path.join(name);
     if let Err(e) = fs::create_dir_all(&table_directory) {
         dbg!(e);
@@ -66,6 +176,10 @@ pub fn create_table(
     let page = File::create(pages.join("0")).unwrap();
     page.set_len(PAGE_SIZE_BYTES as u64).unwrap();
     write_number_of_pages(&table_directory, 1).unwrap();
+    fs::create_dir(table_directory.join(UNIQUE_DIRECTORY_NAME)).unwrap();
+    for constraint in key_constraints(&table_directory, &metadata) {
+        write_key_index(&constraint.index_path, &KeyIndex::new())?;
+    }
 
     let indexes_dir = table_directory.join(INDEXES_DIRECTORY_NAME);
     fs::create_dir(&indexes_dir).unwrap();
@@ -77,15 +191,32 @@ pub fn create_table(
         //let config = bincode::config::standard().with_little_endian();
         //bincode::encode_into_std_write(map, &mut index_file, config).unwrap();
     }
+
+    let sequences_dir = table_directory.join(SEQUENCES_DIRECTORY_NAME);
+    fs::create_dir(&sequences_dir).unwrap();
+    for field in metadata
+        .fields()
+        .iter()
+        .filter(|field| field.auto_increment)
+    {
+        write_sequence(&sequences_dir.join(&field.name), 1)?;
+    }
     Ok(())
 }
 
-pub fn clear_table(table_path: &Path) -> Result<(), String> {
+pub fn clear_table(table_path: &Path) -> Result<(), Error> {
     let free_space_path = table_path.join(FREE_SPACE_FILE_NAME);
     let pages_dir = table_path.join(PAGES_DIRECTORY_NAME);
 
     let metadata = get_table_metadata(table_path)?;
 
+    // Referencing records are handled the same way as when all records are deleted one by
+    // one, the table itself is cleared afterwards anyway
+    if !referencing_tables(table_path)?.is_empty() {
+        let positions = get_all_positions(table_path, &metadata)?;
+        delete_with_references(table_path, positions)?;
+    }
+
     let number_of_pages: u64 = read_number_of_pages(table_path)?;
     for i in 1..number_of_pages {
         let file_path = pages_dir.join(i.to_string());
@@ -95,10 +226,23 @@ pub fn clear_table(table_path: &Path) -> Result<(), String> {
             return Err(format!(
                 "Error while deleting page {}: permission denied",
                 file_path.display()
-            ));
+            )
+            .into());
         }
     }
     write_number_of_pages(table_path, 1)?;
+    if let Err(e) = fs::remove_dir_all(pages_dir.join(OVERFLOW_DIRECTORY_NAME))
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        return Err(format!("Error while deleting overflow pages: {}", e).into());
+    }
+    for constraint in key_constraints(table_path, &metadata) {
+        write_key_index(&constraint.index_path, &KeyIndex::new())?;
+    }
+    let indexes_dir = table_path.join(INDEXES_DIRECTORY_NAME);
+    for field in metadata.fields() {
+        write_index(&indexes_dir.join(&field.name), &BTreeMap::new())?;
+    }
     let number_of_cells_per_page = PAGE_SIZE_BYTES / metadata.record_size();
     if let Err(e) = fs::write(
         free_space_path,
@@ -106,56 +250,106 @@ pub fn clear_table(table_path: &Path) -> Result<(), String> {
             .unwrap()
             .serialize(),
     ) {
-        return Err(format!("Error while writing to free space file: {}", e));
+        return Err(format!("Error while writing to free space file: {}", e).into());
     };
     Ok(())
 }
 
-pub fn delete_table(table_path: &Path) -> Result<(), String> {
+/// Deletes the table unless other tables reference it, references of the table to itself
+/// are deleted together with it
+pub fn delete_table(table_path: &Path) -> Result<(), Error> {
+    let name = table_path.file_name().unwrap_or_default();
+    for (path, metadata) in referencing_tables(table_path)? {
+        if path.file_name() == Some(name) {
+            continue;
+        }
+        if let Some(foreign_key) = metadata
+            .constraints()
+            .foreign_keys
+            .iter()
+            .find(|foreign_key| name == foreign_key.table.as_str())
+        {
+            return Err(Error::ConstraintViolation(
+                foreign_key.name.clone(),
+                format!(
+                    "'{}' table references the deleted table",
+                    path.file_name().unwrap_or_default().to_string_lossy()
+                ),
+            ));
+        }
+    }
     match fs::remove_dir_all(table_path) {
         Ok(()) => Ok(()),
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(Error::Other(e.to_string())),
     }
 }
 
 // Not the primary concern for now
+#[allow(dead_code)]
 pub fn add_index() {
     unimplemented!()
 }
 
 // Not the primary concern for now
+#[allow(dead_code)]
 pub fn delete_index() {
     unimplemented!()
 }
 
-pub fn add_records(table_path: &Path, records: Vec<Vec<Type>>) -> Result<(), String> {
+/// Adds records to the table. If columns are given, records contain values only for them
+/// in the same order, otherwise records contain values for all fields. Omitted values
+/// (either None or values of columns that aren't listed) are generated or take defaults.
+/// Returns primary keys of the added records
+pub fn add_records(
+    table_path: &Path,
+    columns: &[String],
+    records: Vec<Vec<Option<Type>>>,
+) -> Result<Vec<Vec<Type>>, Error> {
     let pages_dir = table_path.join(PAGES_DIRECTORY_NAME);
     let indexes_dir = table_path.join(INDEXES_DIRECTORY_NAME);
     let free_space_path = table_path.join(FREE_SPACE_FILE_NAME);
 
     let table_metadata = get_table_metadata(table_path)?;
 
-    // TODO: validate 'values' vector
-    // if records
-    //     .iter()
-    //     .all(|record| record.len() as u16 == table_metadata.record_size())
-    // {}
-    //values.iter().all(|record| record.iter().all(|value| value.type_id() == table_metadata.));
-
-    // Check new records for primary key uniqueness
-    // TODO: check for pk duplicates in the new ones
-    let pk_idx = table_metadata.primary_key() as usize;
-    let pk_field = &table_metadata.fields()[pk_idx];
-    let pk_index_path = indexes_dir.join(&pk_field.name);
-    let pk_index = read_index(&pk_index_path)?;
-    let all_records_unique = records
+    let mut records = match columns.is_empty() {
+        true => records,
+        false => arrange_records(&table_metadata, columns, records)?,
+    };
+    for (idx, field) in table_metadata.fields().iter().enumerate() {
+        if field.auto_increment {
+            fill_auto_increment(table_path, field, idx, &mut records)?;
+        }
+    }
+    let records = records
+        .into_iter()
+        .map(|record| prepare_record(&table_metadata, record))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // Check uniqueness of the primary key and unique fields, both against stored records
+    // and among the new ones
+    let keys: Vec<Vec<Type>> = records
         .iter()
-        .all(|record| match pk_index.get(&record[pk_idx]) {
-            Some(data) => data.is_empty(),
-            None => true,
-        });
-    if !all_records_unique {
-        return Err(String::from("Error: new records break the pk uniqueness."));
+        .map(|record| table_metadata.primary_key_values(record))
+        .collect();
+    let key_constraints = key_constraints(table_path, &table_metadata);
+    let mut key_indexes = Vec::with_capacity(key_constraints.len());
+    for constraint in &key_constraints {
+        let index = read_key_index(&constraint.index_path)?;
+        let new_keys: Vec<_> = records
+            .iter()
+            .map(|record| constraint.key(record))
+            .collect();
+        check_new_keys(constraint, &index, &new_keys, &HashSet::new())?;
+        key_indexes.push((index, new_keys));
+    }
+    let foreign_keys: Vec<&ForeignKey> = table_metadata.constraints().foreign_keys.iter().collect();
+    check_references(table_path, &foreign_keys, &records, &keys)?;
+    let checks: Vec<&CheckConstraint> = table_metadata.constraints().checks.iter().collect();
+    check_conditions(&table_metadata, &checks, &records)?;
+
+    let mut records = records;
+    for record in &mut records {
+        store_overflow_values(table_path, record)?;
     }
 
     let raw_data = fs::read(&free_space_path).unwrap();
@@ -181,6 +375,7 @@ pub fn add_records(table_path: &Path, records: Vec<Vec<Type>>) -> Result<(), Str
     }
 
     // Writing records
+    let record_structure = table_metadata.record_structure();
     let mut records_position: HashMap<usize, DataPosition> = HashMap::new();
     let mut free_cell_idx: usize = 0;
     let mut current_record: usize = 0;
@@ -192,8 +387,10 @@ pub fn add_records(table_path: &Path, records: Vec<Vec<Type>>) -> Result<(), Str
         let free_position_begining =
             free_space_data[free_cell_idx].begin() * table_metadata.record_size();
 
-        let mut page =
-            File::open(pages_dir.join(free_space_data[free_cell_idx].page.to_string())).unwrap();
+        let mut page = OpenOptions::new()
+            .write(true)
+            .open(pages_dir.join(free_space_data[free_cell_idx].page.to_string()))
+            .unwrap();
 
         page.seek(SeekFrom::Start(free_position_begining as u64))
             .unwrap();
@@ -201,6 +398,7 @@ pub fn add_records(table_path: &Path, records: Vec<Vec<Type>>) -> Result<(), Str
         for i in 0..records_to_add as usize {
             page.write_all(&structures::dbtype::serialize_values(
                 &records[current_record + i],
+                &record_structure,
             ))
             .unwrap();
             records_position.insert(
@@ -228,10 +426,19 @@ pub fn add_records(table_path: &Path, records: Vec<Vec<Type>>) -> Result<(), Str
     .unwrap();
 
     // Updating indexes
+    for (constraint, (mut index, new_keys)) in key_constraints.iter().zip(key_indexes) {
+        for (i, key) in new_keys.into_iter().enumerate() {
+            if let Some(key) = key {
+                index.insert(key, records_position.get(&i).unwrap().clone());
+            }
+        }
+        write_key_index(&constraint.index_path, &index)?;
+    }
     let fields = table_metadata.fields();
     //let config = bincode::config::standard().with_little_endian();
     for &index_idx in table_metadata.indexes_idx() {
-        let index_name = &fields[index_idx as usize].name;
+        let field = &fields[index_idx as usize];
+        let index_name = &field.name;
         // let mut index_file = File::open(indexes_dir.join(index_name)).unwrap();
         // let mut index_map: BTreeMap<Type, Vec<DataPosition>> =
         //     bincode::decode_from_std_read(&mut index_file, config).unwrap();
@@ -239,90 +446,161 @@ pub fn add_records(table_path: &Path, records: Vec<Vec<Type>>) -> Result<(), Str
         let mut index_map = read_index(&index_path)?;
         for (i, record) in records.iter().enumerate() {
             index_map
-                .entry(record[index_idx as usize].clone())
+                .entry(field.collation.key(&record[index_idx as usize]))
                 .or_insert_with(Vec::new)
                 .push(records_position.get(&i).unwrap().clone());
         }
         // bincode::encode_into_std_write(index_map, &mut index_file, config).unwrap();
         write_index(&index_path, &index_map).unwrap();
     }
-    Ok(())
+    Ok(keys)
 }
 
-pub fn get_records(table_path: &Path, filters: &[FilterOption]) -> Result<Vec<Vec<Type>>, String> {
+pub fn get_records(table_path: &Path, filter: &FilterExpression) -> Result<Vec<Vec<Type>>, String> {
     let metadata = get_table_metadata(table_path)?;
-    let positions = get_positions(table_path, filters).unwrap();
-    let db_structure: Vec<Type> = metadata
-        .fields()
-        .iter()
-        .map(|field| field.type_.clone())
-        .collect();
-    get_records_by_position(
+    let positions = get_positions(table_path, filter)?;
+    let db_structure = metadata.record_structure();
+    let mut records = get_records_by_position(
         table_path,
         &positions,
         &db_structure,
         metadata.record_size(),
-    )
+    )?;
+    for record in &mut records {
+        for (value, type_) in record.iter_mut().zip(&db_structure) {
+            load_overflow_value(table_path, value, type_)?;
+        }
+    }
+    Ok(records)
 }
 
-pub fn delete_records(table_path: &Path, filters: &[FilterOption]) -> Result<(), String> {
-    let pages_dir = table_path.join(PAGES_DIRECTORY_NAME);
-
-    let metadata = get_table_metadata(table_path)?;
+pub fn delete_records(table_path: &Path, filter: &FilterExpression) -> Result<(), Error> {
+    let record_positions = get_positions(table_path, filter)?;
+    delete_with_references(table_path, record_positions)
+}
 
-    let record_positions = get_positions(table_path, filters)?;
-    let record_positions_set: HashSet<DataPosition> =
-        record_positions.clone().into_iter().collect();
+/// Deletes records at the positions and applies the referential actions of foreign keys
+/// that reference them
+fn delete_with_references(
+    table_path: &Path,
+    record_positions: Vec<DataPosition>,
+) -> Result<(), Error> {
     if record_positions.is_empty() {
         return Ok(());
     }
 
-    // Delete indexes
-    let fields = metadata.fields();
-    let records_structure: Vec<Type> = fields.iter().map(|filed| filed.type_.clone()).collect();
-    let record_size = records_structure
+    // Collect records of all tables that are deleted by cascading foreign keys first, so
+    // nothing is deleted if any of them is referenced by a restricting one
+    let mut deleted: HashMap<PathBuf, HashSet<DataPosition>> = HashMap::new();
+    let mut restricted = Vec::new();
+    let mut set_null = Vec::new();
+    let mut queue = vec![(table_path.to_path_buf(), record_positions)];
+    while let Some((path, positions)) = queue.pop() {
+        let planned = deleted.entry(path.clone()).or_default();
+        let positions: Vec<DataPosition> = positions
+            .into_iter()
+            .filter(|position| planned.insert(position.clone()))
+            .collect();
+        if positions.is_empty() {
+            continue;
+        }
+        let metadata = get_table_metadata(&path)?;
+        let primary_key = primary_key_constraint(&path, &metadata);
+        let keys: BTreeSet<Vec<Type>> = get_records_by_position(
+            &path,
+            &positions,
+            &metadata.record_structure(),
+            metadata.record_size(),
+        )?
         .iter()
-        .map(|field| field.size() as u16)
-        .sum();
-    let records = get_records_by_position(
-        table_path,
-        &record_positions,
-        &records_structure,
-        record_size,
-    )
-    .unwrap();
+        .filter_map(|record| primary_key.key(record))
+        .collect();
+        for reference in find_references(&path, &primary_key, &keys)? {
+            match reference.foreign_key.on_delete {
+                ReferentialAction::Restrict => restricted.push(reference),
+                ReferentialAction::Cascade => {
+                    queue.push((reference.table_path, reference.positions));
+                }
+                ReferentialAction::SetNull => set_null.push(reference),
+            }
+        }
+    }
+    let is_deleted = |path: &Path, position: &DataPosition| {
+        deleted
+            .get(path)
+            .is_some_and(|positions| positions.contains(position))
+    };
+    for reference in &restricted {
+        if reference
+            .positions
+            .iter()
+            .any(|position| !is_deleted(&reference.table_path, position))
+        {
+            let table = reference.table_path.file_name().unwrap_or_default();
+            return Err(Error::ConstraintViolation(
+                reference.foreign_key.name.clone(),
+                format!(
+                    "records of '{}' table reference deleted records",
+                    table.to_string_lossy()
+                ),
+            ));
+        }
+    }
 
-    for &index_idx in metadata.indexes_idx() {
-        let index_idx = index_idx as usize;
-        let values_range: BTreeSet<&Type> =
-            records.iter().map(|record| &record[index_idx]).collect();
-        let index_path = pages_dir.join(&fields[index_idx].name);
-        let mut index = read_index(&index_path)?;
-        for value in values_range {
-            if let Some(positions) = index.get_mut(value) {
-                positions.retain(|position| !record_positions_set.contains(position));
+    // Records that stay get nulls in the fields of all foreign keys referencing deleted
+    // records at once. The new records are checked before anything is deleted
+    let mut nulled_fields: HashMap<PathBuf, HashMap<DataPosition, BTreeSet<usize>>> =
+        HashMap::new();
+    for reference in set_null {
+        for position in reference.positions {
+            if is_deleted(&reference.table_path, &position) {
+                continue;
             }
+            nulled_fields
+                .entry(reference.table_path.clone())
+                .or_default()
+                .entry(position)
+                .or_default()
+                .extend(reference.foreign_key.fields.iter().map(|&idx| idx as usize));
         }
-        write_index(&index_path, &index)?;
     }
-    delete_records_by_position(table_path, &record_positions)
+    let mut changes = Vec::new();
+    for (path, positions) in nulled_fields {
+        let metadata = get_table_metadata(&path)?;
+        let mut groups: BTreeMap<BTreeSet<usize>, Vec<DataPosition>> = BTreeMap::new();
+        for (position, fields) in positions {
+            groups.entry(fields).or_default().push(position);
+        }
+        for (fields, positions) in groups {
+            let values = fields.into_iter().map(|idx| (idx, Type::Null)).collect();
+            let prepared = prepare_changes(&path, &metadata, positions, values)?;
+            changes.push((path.clone(), metadata.clone(), prepared));
+        }
+    }
+
+    for (path, positions) in &deleted {
+        remove_records(path, positions.iter().cloned().collect())?;
+    }
+    for (path, metadata, prepared) in changes {
+        write_changes(&path, &metadata, prepared)?;
+    }
+    Ok(())
 }
 
 pub fn change_records(
     table_path: &Path,
-    filters: &[FilterOption],
+    filter: &FilterExpression,
     structure: Vec<Field>,
     values: Vec<Type>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if values.len() != structure.len() {
-        return Err(format!(
+        return Err(Error::Other(format!(
             "Structure of element to change and their values have different length: structure {}; values {}",
             structure.len(),
             values.len()
-        ));
+        )));
     }
 
-    let indexes_dir = table_path.join(INDEXES_DIRECTORY_NAME);
     let metadata = get_table_metadata(table_path)?;
     let fileds_map: HashMap<&String, usize> = metadata
         .fields()
@@ -330,85 +608,36 @@ pub fn change_records(
         .enumerate()
         .map(|(idx, field)| (&field.name, idx))
         .collect();
-    let field_name_to_value_map: HashMap<String, Type> = structure
-        .iter()
-        .zip(values.iter())
-        .map(|(field, value)| (field.name.clone(), value.clone()))
-        .collect();
 
     // Validating request and converting to appropriate data structure
     let mut index_value = Vec::with_capacity(structure.len());
-    for (field_to_change, value) in structure.iter().zip(values.into_iter()) {
+    for (field_to_change, value) in structure.iter().zip(values) {
         match fileds_map.get(&field_to_change.name) {
             Some(&idx) => {
                 // For now, changing primary key is not allowed. This behaviour is likely to change in the future
-                if idx == metadata.primary_key() as usize {
-                    return Err(String::from("Cannot change primary key field"));
+                if metadata.primary_key().contains(&(idx as u16)) {
+                    return Err(Error::from("Cannot change primary key field"));
                 }
+                if metadata.fields()[idx].generated.is_some() {
+                    return Err(Error::Other(format!(
+                        "Field '{}' is generated and cannot be changed",
+                        field_to_change.name
+                    )));
+                }
+                let value = metadata.fields()[idx].prepare_value(value)?;
                 index_value.push((idx, value))
             }
             None => {
-                return Err(format!(
+                return Err(Error::Other(format!(
                     "Attempted to change a field that doesn't exist in the record scheme: {}",
                     field_to_change.name,
-                ));
-            }
-        }
-    }
-
-    let indexed_fields_hash_set: HashSet<&String> = metadata
-        .indexes()
-        .iter()
-        .map(|&field| &field.name)
-        .collect();
-    let filtered_fields_hash_set: HashSet<&String> =
-        structure.iter().map(|field| &field.name).collect();
-
-    let indexes_to_update: Vec<&String> = (&indexed_fields_hash_set & &filtered_fields_hash_set)
-        .into_iter()
-        .collect();
-
-    let old_values_positions = indexes_to_update
-        .iter()
-        .filter_map(|&field| fileds_map.get(field).copied())
-        .collect();
-
-    let records_positions = get_positions(table_path, filters)?;
-    let old_values = change_records_by_position(
-        table_path,
-        &metadata,
-        records_positions,
-        index_value,
-        old_values_positions,
-    )?;
-
-    // Update indexes
-    for (&index_name, (positions_of_changed_records, old_values)) in
-        indexes_to_update.iter().zip(old_values)
-    {
-        let index_path = indexes_dir.join(index_name);
-        let mut index = read_index(&index_path)?;
-        for value in old_values {
-            if let Some(indexed_positions) = index.get_mut(&value) {
-                indexed_positions.retain(|pos| !positions_of_changed_records.contains(pos));
-            }
-        }
-        let new_value = field_name_to_value_map.get(index_name).unwrap();
-        match index.get_mut(new_value) {
-            Some(indexed_positions) => {
-                indexed_positions.append(&mut positions_of_changed_records.into_iter().collect());
-            }
-            None => {
-                index.insert(
-                    new_value.clone(),
-                    positions_of_changed_records.into_iter().collect(),
-                );
+                )));
             }
         }
-        write_index(&index_path, &index)?;
     }
 
-    Ok(())
+    let records_positions = get_positions(table_path, filter)?;
+    change_records_at(table_path, &metadata, records_positions, index_value)
 }
 
 fn get_table_metadata(table_path: &Path) -> Result<TableMetadata, String> {
@@ -423,132 +652,739 @@ fn get_table_metadata(table_path: &Path) -> Result<TableMetadata, String> {
     }
 }
 
-fn get_positions(table_path: &Path, filters: &[FilterOption]) -> Result<Vec<DataPosition>, String> {
-    let indexes_dir = table_path.join(INDEXES_DIRECTORY_NAME);
-    let metadata = get_table_metadata(table_path)?;
-
-    if filters.is_empty() {
-        return get_all_positions(table_path, &metadata);
+/// Converts records that contain values only for the given columns to records with all fields
+fn arrange_records(
+    metadata: &TableMetadata,
+    columns: &[String],
+    records: Vec<Vec<Option<Type>>>,
+) -> Result<Vec<Vec<Option<Type>>>, String> {
+    let fields = metadata.fields();
+    let mut positions = Vec::with_capacity(columns.len());
+    for column in columns {
+        let position = match fields.iter().position(|field| &field.name == column) {
+            Some(position) => position,
+            None => return Err(format!("Table doesn't have '{}' field", column)),
+        };
+        if positions.contains(&position) {
+            return Err(format!("Field '{}' is listed more than once", column));
+        }
+        positions.push(position);
     }
+    records
+        .into_iter()
+        .map(|record| {
+            if record.len() != columns.len() {
+                return Err(format!(
+                    "Record has {} values while {} columns are listed",
+                    record.len(),
+                    columns.len()
+                ));
+            }
+            let mut full_record = vec![None; fields.len()];
+            for (&position, value) in positions.iter().zip(record) {
+                full_record[position] = value;
+            }
+            Ok(full_record)
+        })
+        .collect()
+}
 
-    // TODO: check if filter option is valid (fileds corresponds to the exicting ones)
-
-    // if there are indexed filters check all of them first (if not than get all records)
-    // for every other field do a linear search
-
-    let indexed_fields: HashSet<String> = metadata
-        .indexes()
+/// Checks that the record matches table structure, generates omitted values, brings
+/// values to the form of the fields and computes generated ones
+fn prepare_record(
+    metadata: &TableMetadata,
+    record: Vec<Option<Type>>,
+) -> Result<Vec<Type>, String> {
+    let fields = metadata.fields();
+    if record.len() != fields.len() {
+        return Err(format!(
+            "Record has {} values while table has {} fields",
+            record.len(),
+            fields.len()
+        ));
+    }
+    let mut record = fields
         .iter()
-        .map(|&field| field.name.clone())
-        .collect();
-    let mut indexed_filters = Vec::new();
-    let mut unindexed_filters = Vec::new();
-    for filter in filters {
-        if indexed_fields.contains(&filter.field().name) {
-            indexed_filters.push(filter);
-        } else {
-            unindexed_filters.push(filter);
+        .zip(record)
+        .map(|(field, value)| match (value, &field.generated) {
+            (Some(_), Some(_)) => Err(format!(
+                "Field '{}' is generated and cannot be set",
+                field.name
+            )),
+            // Computed once the other values are ready
+            (None, Some(_)) => Ok(Type::Null),
+            (Some(value), None) => field.prepare_value(value),
+            (None, None) => field.missing_value(),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for (idx, field) in fields.iter().enumerate() {
+        if field.generated.is_some() {
+            record[idx] = field.compute_value(&record)?;
         }
     }
+    Ok(record)
+}
 
-    let mut positions: HashSet<DataPosition>;
-    if indexed_filters.is_empty() {
-        positions = get_all_positions(table_path, &metadata)
-            .unwrap()
-            .into_iter()
-            .collect();
-    } else {
-        // TODO: Skip one iteration in for loop and move it here
-        positions = get_all_positions(table_path, &metadata)
-            .unwrap()
-            .into_iter()
-            .collect();
-    }
-    for filter in indexed_filters {
-        let index_path = indexes_dir.join(&filter.field().name);
-        let index = read_index(&index_path)?;
-        match filter.filter() {
-            Filter::Equal => {
-                match index.get(&filter.field().type_) {
-                    Some(new_positions) => {
-                        if new_positions.is_empty() {
-                            return Ok(Vec::new());
-                        }
-                        positions = positions
-                            .intersection(&HashSet::from_iter(new_positions.clone().into_iter()))
-                            .cloned()
-                            .collect();
-                    }
-                    None => return Ok(Vec::new()),
-                };
-            }
-            _ => unimplemented!(),
+/// Gives values of an auto increment field that are omitted in the records the next values
+/// of its sequence. The sequence is moved past values given explicitly
+fn fill_auto_increment(
+    table_path: &Path,
+    field: &Field,
+    field_idx: usize,
+    records: &mut [Vec<Option<Type>>],
+) -> Result<(), String> {
+    let mut missing = 0;
+    let mut used = None;
+    for value in records.iter().filter_map(|record| record.get(field_idx)) {
+        match value {
+            Some(value) => used = used.max(value.integer_value()),
+            None => missing += 1,
         }
     }
-    for filter in unindexed_filters {
-        linear_search(&mut positions, table_path, filter, &metadata).unwrap();
+    if missing == 0 && used.is_none() {
+        return Ok(());
     }
-
-    Ok(positions.into_iter().collect())
+    let first = take_sequence_values(table_path, &field.name, missing, used)?;
+    let missing_values = records
+        .iter_mut()
+        .filter_map(|record| record.get_mut(field_idx))
+        .filter(|value| value.is_none());
+    for (next, value) in (first..).zip(missing_values) {
+        match field.type_.with_integer_value(next) {
+            Some(generated) => *value = Some(generated),
+            None => return Err(format!("Sequence of '{}' field is exhausted", field.name)),
+        }
+    }
+    Ok(())
 }
 
-fn get_records_by_position(
+/// Takes `count` values of the sequence and returns the first of them. Values that are not
+/// greater than `used` are skipped
+fn take_sequence_values(
     table_path: &Path,
-    positions: &[DataPosition],
-    record_structure: &[Type],
-    record_size: u16,
-) -> Result<Vec<Vec<Type>>, String> {
-    if positions.is_empty() {
-        return Ok(Vec::new());
+    name: &str,
+    count: u64,
+    used: Option<i128>,
+) -> Result<i128, String> {
+    let sequence_path = table_path.join(SEQUENCES_DIRECTORY_NAME).join(name);
+    let _guard = SEQUENCES_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut first = read_sequence(&sequence_path)?;
+    if let Some(used) = used {
+        first = first.max(used.saturating_add(1));
     }
+    write_sequence(&sequence_path, first.saturating_add(count as i128))?;
+    Ok(first)
+}
 
-    let page_dir = table_path.join(PAGES_DIRECTORY_NAME);
-    let mut positions = positions.to_vec();
-    positions.sort_by_key(|position| (position.page, position.cell));
+/// Primary key and unique constraints of the table
+fn key_constraints(table_path: &Path, metadata: &TableMetadata) -> Vec<KeyConstraint> {
+    let unique_dir = table_path.join(UNIQUE_DIRECTORY_NAME);
+    let primary_key = primary_key_constraint(table_path, metadata);
+    let unique = metadata.constraints().unique.iter().map(|constraint| {
+        KeyConstraint::new(
+            constraint.name.clone(),
+            constraint.fields.clone(),
+            metadata,
+            unique_dir.join(&constraint.name),
+        )
+    });
+    std::iter::once(primary_key).chain(unique).collect()
+}
 
-    let mut current_page_idx = positions[0].page;
-    let page_path = page_dir.join(current_page_idx.to_string());
-    let mut current_page = File::open(page_path).unwrap();
-    let mut values = Vec::with_capacity(positions.len());
-    for position in positions {
-        if position.page != current_page_idx {
-            current_page_idx = position.page;
-            let page_path = page_dir.join(current_page_idx.to_string());
-            current_page = File::open(page_path).unwrap();
+fn primary_key_constraint(table_path: &Path, metadata: &TableMetadata) -> KeyConstraint {
+    KeyConstraint::new(
+        String::from(PRIMARY_KEY_FILE_NAME),
+        metadata.primary_key().to_vec(),
+        metadata,
+        table_path.join(PRIMARY_KEY_FILE_NAME),
+    )
+}
+
+/// Checks that new keys don't repeat each other and keys in the index. Keys of records
+/// at the replaced positions are going to be changed, so they don't conflict
+fn check_new_keys(
+    constraint: &KeyConstraint,
+    index: &KeyIndex,
+    new_keys: &[Option<Vec<Type>>],
+    replaced: &HashSet<DataPosition>,
+) -> Result<(), Error> {
+    let mut seen = BTreeSet::new();
+    for key in new_keys.iter().flatten() {
+        let stored = index
+            .get(key)
+            .is_some_and(|position| !replaced.contains(position));
+        if stored || !seen.insert(key) {
+            return Err(Error::ConstraintViolation(
+                constraint.name.clone(),
+                format!("key {:?} is duplicated", key),
+            ));
         }
-        current_page
-            .seek(SeekFrom::Start(position.cell as u64))
-            .unwrap();
-        let mut buffer = Vec::with_capacity(record_size as usize);
-        current_page.read_exact(&mut buffer).unwrap();
-        values.push(structures::dbtype::deserialize_value(&buffer, record_structure).unwrap());
     }
-
-    Ok(values)
+    Ok(())
 }
 
-fn linear_search(
-    data: &mut HashSet<DataPosition>,
-    table_path: &Path,
-    filter: &FilterOption,
+/// Checks that foreign keys reference primary keys of existing tables of the same types
+fn check_foreign_keys(
+    database_path: &Path,
+    name: &str,
     metadata: &TableMetadata,
-) -> Result<(), &'static str> {
-    let pages_dir = table_path.join(PAGES_DIRECTORY_NAME);
-    let mut data_vec: Vec<DataPosition> = data.clone().into_iter().collect();
-    data_vec.sort_by_key(|position| position.page);
+) -> Result<(), String> {
+    for foreign_key in &metadata.constraints().foreign_keys {
+        let parent_path = database_path.join(&foreign_key.table);
+        let parent = if foreign_key.table == name {
+            metadata.clone()
+        } else if dir_is_table(&parent_path) {
+            get_table_metadata(&parent_path)?
+        } else {
+            return Err(format!(
+                "Table '{}' referenced by '{}' foreign key doesn't exist",
+                foreign_key.table, foreign_key.name
+            ));
+        };
+        let same_types = foreign_key.fields.len() == parent.primary_key().len()
+            && foreign_key
+                .fields
+                .iter()
+                .zip(parent.primary_key())
+                .all(|(&field, &key)| {
+                    metadata.fields()[field as usize]
+                        .type_
+                        .serialize_definition()
+                        == parent.fields()[key as usize].type_.serialize_definition()
+                });
+        if !same_types {
+            return Err(format!(
+                "Fields of '{}' foreign key don't match the primary key of '{}' table",
+                foreign_key.name, foreign_key.table
+            ));
+        }
+    }
+    Ok(())
+}
 
-    let record_structure: Vec<Type> = metadata
-        .fields()
+/// Checks that records reference existing records of the tables their foreign keys point
+/// to. Records can also reference records of the same table by `own_keys`, primary keys of
+/// records that are added along with them
+fn check_references(
+    table_path: &Path,
+    foreign_keys: &[&ForeignKey],
+    records: &[Vec<Type>],
+    own_keys: &[Vec<Type>],
+) -> Result<(), Error> {
+    for foreign_key in foreign_keys {
+        let parent_path = table_path.with_file_name(&foreign_key.table);
+        let primary_key = primary_key_constraint(&parent_path, &get_table_metadata(&parent_path)?);
+        let index = read_key_index(&primary_key.index_path)?;
+        let own_keys: BTreeSet<Vec<Type>> = match parent_path == table_path {
+            true => own_keys
+                .iter()
+                .filter_map(|key| primary_key.key_of(key.iter()))
+                .collect(),
+            false => BTreeSet::new(),
+        };
+        for record in records {
+            let values = foreign_key.fields.iter().map(|&idx| &record[idx as usize]);
+            // Keys with nulls don't reference anything
+            let Some(key) = primary_key.key_of(values) else {
+                continue;
+            };
+            if index.contains_key(&key) || own_keys.contains(&key) {
+                continue;
+            }
+            return Err(Error::ConstraintViolation(
+                foreign_key.name.clone(),
+                format!(
+                    "key {:?} doesn't exist in '{}' table",
+                    key, foreign_key.table
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that records meet conditions of the checks
+fn check_conditions(
+    metadata: &TableMetadata,
+    checks: &[&CheckConstraint],
+    records: &[Vec<Type>],
+) -> Result<(), Error> {
+    for check in checks {
+        if let Some(record) = records
+            .iter()
+            .find(|record| !check.holds(record, metadata.fields()[check.field as usize].collation))
+        {
+            return Err(Error::ConstraintViolation(
+                check.name.clone(),
+                format!(
+                    "record with key {:?} doesn't meet the condition on '{}' field",
+                    metadata.primary_key_values(record),
+                    metadata.fields()[check.field as usize].name
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Tables of the same database that have foreign keys referencing the table, only their
+/// descriptors are read
+fn referencing_tables(table_path: &Path) -> Result<Vec<(PathBuf, TableMetadata)>, String> {
+    let (Some(database_path), Some(name)) = (table_path.parent(), table_path.file_name()) else {
+        return Err(format!("Invalid table path: {}", table_path.display()));
+    };
+    let mut tables = Vec::new();
+    for table in get_table_list(database_path)? {
+        let path = database_path.join(&table);
+        let metadata = get_table_metadata(&path)?;
+        if metadata
+            .constraints()
+            .foreign_keys
+            .iter()
+            .any(|foreign_key| name == foreign_key.table.as_str())
+        {
+            tables.push((path, metadata));
+        }
+    }
+    Ok(tables)
+}
+
+/// Finds records of the tables in the same database that reference records of the table
+/// with the given primary keys, the keys are compared in the form of the key index
+fn find_references(
+    table_path: &Path,
+    primary_key: &KeyConstraint,
+    keys: &BTreeSet<Vec<Type>>,
+) -> Result<Vec<Reference>, String> {
+    let name = table_path.file_name().unwrap_or_default();
+    let mut references = Vec::new();
+    for (child_path, metadata) in referencing_tables(table_path)? {
+        for foreign_key in metadata
+            .constraints()
+            .foreign_keys
+            .iter()
+            .filter(|foreign_key| name == foreign_key.table.as_str())
+        {
+            let positions =
+                match indexed_references(&child_path, &metadata, foreign_key, primary_key, keys)? {
+                    Some(positions) => positions,
+                    None => get_all_positions(&child_path, &metadata)?,
+                };
+            let records = get_records_by_position(
+                &child_path,
+                &positions,
+                &metadata.record_structure(),
+                metadata.record_size(),
+            )?;
+            let referencing: Vec<DataPosition> = positions
+                .iter()
+                .zip(&records)
+                .filter(|(_, record)| {
+                    let values = foreign_key.fields.iter().map(|&idx| &record[idx as usize]);
+                    primary_key
+                        .key_of(values)
+                        .is_some_and(|key| keys.contains(&key))
+                })
+                .map(|(position, _)| position.clone())
+                .collect();
+            if !referencing.is_empty() {
+                references.push(Reference {
+                    table_path: child_path.clone(),
+                    foreign_key: foreign_key.clone(),
+                    positions: referencing,
+                });
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// Positions of records that may reference the keys, found by the index of a field of the
+/// foreign key. None if no field of the foreign key is indexed with the collation of the
+/// referenced field, the records have to be scanned then. Positions are sorted the way
+/// records are read
+fn indexed_references(
+    child_path: &Path,
+    metadata: &TableMetadata,
+    foreign_key: &ForeignKey,
+    primary_key: &KeyConstraint,
+    keys: &BTreeSet<Vec<Type>>,
+) -> Result<Option<Vec<DataPosition>>, String> {
+    let indexed = metadata.indexes_idx();
+    let Some((i, &field_idx)) = foreign_key.fields.iter().enumerate().find(|&(i, idx)| {
+        indexed.contains(idx)
+            && metadata.fields()[*idx as usize].collation == primary_key.collations[i]
+    }) else {
+        return Ok(None);
+    };
+    let field = &metadata.fields()[field_idx as usize];
+    let index = read_index(&child_path.join(INDEXES_DIRECTORY_NAME).join(&field.name))?;
+    let positions: HashSet<DataPosition> = keys
+        .iter()
+        .filter_map(|key| index.get(&key[i]))
+        .flatten()
+        .cloned()
+        .collect();
+    let mut positions: Vec<DataPosition> = positions.into_iter().collect();
+    positions.sort_by_key(|position| (position.page, position.cell));
+    Ok(Some(positions))
+}
+
+fn get_positions(
+    table_path: &Path,
+    filter: &FilterExpression,
+) -> Result<Vec<DataPosition>, String> {
+    let metadata = get_table_metadata(table_path)?;
+    let mut all = None;
+    let positions = find_positions(table_path, &metadata, filter, &mut all)?;
+    Ok(positions.into_iter().collect())
+}
+
+/// Positions of records that match the expression. Positions of all records are only read
+/// if they are needed and only once
+fn find_positions(
+    table_path: &Path,
+    metadata: &TableMetadata,
+    filter: &FilterExpression,
+    all: &mut Option<HashSet<DataPosition>>,
+) -> Result<HashSet<DataPosition>, String> {
+    match filter {
+        FilterExpression::Filter(filter) => match_all(table_path, metadata, &[filter], &[], all),
+        FilterExpression::And(expressions) => {
+            let mut filters = Vec::new();
+            let mut nested = Vec::new();
+            for expression in expressions {
+                match expression {
+                    FilterExpression::Filter(filter) => filters.push(filter),
+                    expression => nested.push(expression),
+                }
+            }
+            match_all(table_path, metadata, &filters, &nested, all)
+        }
+        // Every branch is answered on its own, by indexes where it's possible
+        FilterExpression::Or(expressions) => {
+            let mut positions = HashSet::new();
+            for expression in expressions {
+                positions.extend(find_positions(table_path, metadata, expression, all)?);
+            }
+            Ok(positions)
+        }
+        FilterExpression::Not(expression) => {
+            let excluded = find_positions(table_path, metadata, expression, all)?;
+            let all = all_positions(table_path, metadata, all)?;
+            Ok(all.difference(&excluded).cloned().collect())
+        }
+    }
+}
+
+fn all_positions<'a>(
+    table_path: &Path,
+    metadata: &TableMetadata,
+    all: &'a mut Option<HashSet<DataPosition>>,
+) -> Result<&'a HashSet<DataPosition>, String> {
+    match all {
+        Some(all) => Ok(all),
+        None => {
+            let positions = get_all_positions(table_path, metadata)?;
+            Ok(all.insert(positions.into_iter().collect()))
+        }
+    }
+}
+
+/// Positions of records that match all the filters and the nested expressions
+fn match_all(
+    table_path: &Path,
+    metadata: &TableMetadata,
+    filters: &[&FilterOption],
+    nested: &[&FilterExpression],
+    all: &mut Option<HashSet<DataPosition>>,
+) -> Result<HashSet<DataPosition>, String> {
+    let indexes_dir = table_path.join(INDEXES_DIRECTORY_NAME);
+
+    // Indexed filters are answered by the indexes first, then nested expressions narrow
+    // down the records. The records found (or all records if there are none) are then
+    // checked against the rest by a linear search. Range filters on the same field are
+    // merged into a single walk over the index
+
+    let indexed_fields: HashSet<String> = metadata
+        .indexes()
         .iter()
-        .map(|field| field.type_.clone())
+        .map(|&field| field.name.clone())
         .collect();
-    let record_size: u16 = record_structure.iter().map(|t| t.size() as u16).sum();
-    let mut number_of_filtered_field = 0;
-    for (i, field) in metadata.fields().iter().enumerate() {
-        if field.name == filter.field().name {
-            number_of_filtered_field = i;
+    let mut indexed_filters = Vec::new();
+    let mut ranges: BTreeMap<usize, Vec<(Filter, Type)>> = BTreeMap::new();
+    let mut unindexed_filters = Vec::new();
+    for &filter in filters {
+        let (field_idx, operands) = filter_operands(metadata, filter)?;
+        if !indexed_fields.contains(filter.column()) {
+            unindexed_filters.push((filter, field_idx, operands));
+            continue;
+        }
+        // Keys are kept in the folded form of the collation, so they are compared with
+        // the folded operands as they are
+        let collation = metadata.fields()[field_idx].collation;
+        let operands: Vec<Type> = operands
+            .iter()
+            .map(|operand| collation.key(operand))
+            .collect();
+        match filter.filter() {
+            Filter::Less | Filter::Greater | Filter::LessEq | Filter::GreaterEq => {
+                let range = ranges.entry(field_idx).or_default();
+                range.extend(
+                    operands
+                        .into_iter()
+                        .map(|operand| (*filter.filter(), operand)),
+                );
+            }
+            // The bounds are walked together with other range filters on the field
+            Filter::Between => {
+                let range = ranges.entry(field_idx).or_default();
+                range.extend(
+                    [Filter::GreaterEq, Filter::LessEq]
+                        .into_iter()
+                        .zip(operands),
+                );
+            }
+            _ => indexed_filters.push((filter, operands)),
+        }
+    }
+
+    let mut positions: Option<HashSet<DataPosition>> = None;
+    for (filter, operands) in indexed_filters {
+        let index = read_index(&indexes_dir.join(filter.column()))?;
+        let found = match filter.filter() {
+            Filter::Equal => index
+                .get(&operands[0])
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+            // Null values are kept in the index under the 'Null' key, which is the least one
+            Filter::IsNull => index
+                .get(&Type::Null)
+                .into_iter()
+                .flatten()
+                .cloned()
+                .collect(),
+            // A point lookup for every listed value
+            Filter::In => operands
+                .iter()
+                .flat_map(|operand| index.get(operand).into_iter().flatten())
+                .cloned()
+                .collect(),
+            Filter::IsNotNull => index
+                .range((Bound::Excluded(Type::Null), Bound::Unbounded))
+                .flat_map(|(_, found)| found.iter().cloned())
+                .collect(),
+            &filter => index
+                .iter()
+                .filter(|(key, _)| value_matches(filter, key, &operands, Collation::Binary))
+                .flat_map(|(_, found)| found.iter().cloned())
+                .collect(),
+        };
+        narrow(&mut positions, found);
+    }
+    for (field_idx, bounds) in ranges {
+        let index = read_index(&indexes_dir.join(&metadata.fields()[field_idx].name))?;
+        let found = match index_range(&bounds) {
+            Some(range) => index
+                .range(range)
+                .flat_map(|(_, found)| found.iter().cloned())
+                .collect(),
+            None => HashSet::new(),
+        };
+        narrow(&mut positions, found);
+    }
+    for &expression in nested {
+        let found = find_positions(table_path, metadata, expression, all)?;
+        narrow(&mut positions, found);
+    }
+    let mut positions = match positions {
+        Some(positions) => positions,
+        None => all_positions(table_path, metadata, all)?.clone(),
+    };
+    for (filter, field_idx, operands) in unindexed_filters {
+        linear_search(
+            &mut positions,
+            table_path,
+            filter,
+            (field_idx, &operands),
+            metadata,
+        )?;
+    }
+
+    Ok(positions)
+}
+
+/// Leaves only the positions that are also found by another filter
+fn narrow(positions: &mut Option<HashSet<DataPosition>>, found: HashSet<DataPosition>) {
+    *positions = Some(match positions.take() {
+        Some(positions) => positions.intersection(&found).cloned().collect(),
+        None => found,
+    });
+}
+
+/// Range of index keys that meet all the range filters, None if no key does. Nulls are
+/// the least keys and never meet a comparison, so they are always left out
+fn index_range(filters: &[(Filter, Type)]) -> Option<(Bound<Type>, Bound<Type>)> {
+    // The lower bound with whether it's excluded and the upper bound with whether it's
+    // included, so that a tighter lower bound is greater and a tighter upper bound is less
+    let mut lower = (Type::Null, true);
+    let mut upper: Option<(Type, bool)> = None;
+    for (filter, operand) in filters {
+        match filter {
+            Filter::Greater | Filter::GreaterEq => {
+                let bound = (operand.clone(), *filter == Filter::Greater);
+                if bound > lower {
+                    lower = bound;
+                }
+            }
+            _ => {
+                let bound = (operand.clone(), *filter == Filter::LessEq);
+                if upper.as_ref().is_none_or(|upper| bound < *upper) {
+                    upper = Some(bound);
+                }
+            }
         }
     }
+    let (lower, excluded) = lower;
+    let Some((upper, included)) = upper else {
+        return Some((bound(lower, !excluded), Bound::Unbounded));
+    };
+    // BTreeMap::range panics on ranges with the start after the end
+    let empty = match !excluded && included {
+        true => lower > upper,
+        false => lower >= upper,
+    };
+    match empty {
+        true => None,
+        false => Some((bound(lower, !excluded), bound(upper, included))),
+    }
+}
+
+fn bound(key: Type, included: bool) -> Bound<Type> {
+    match included {
+        true => Bound::Included(key),
+        false => Bound::Excluded(key),
+    }
+}
+
+fn get_records_by_position(
+    table_path: &Path,
+    positions: &[DataPosition],
+    record_structure: &[Type],
+    record_size: u16,
+) -> Result<Vec<Vec<Type>>, String> {
+    if positions.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let page_dir = table_path.join(PAGES_DIRECTORY_NAME);
+    let mut positions = positions.to_vec();
+    positions.sort_by_key(|position| (position.page, position.cell));
+
+    let mut current_page_idx = positions[0].page;
+    let page_path = page_dir.join(current_page_idx.to_string());
+    let mut current_page = File::open(page_path).unwrap();
+    let mut values = Vec::with_capacity(positions.len());
+    for position in positions {
+        if position.page != current_page_idx {
+            current_page_idx = position.page;
+            let page_path = page_dir.join(current_page_idx.to_string());
+            current_page = File::open(page_path).unwrap();
+        }
+        current_page
+            .seek(SeekFrom::Start(position.cell as u64))
+            .unwrap();
+        let mut buffer = vec![0u8; record_size as usize];
+        current_page.read_exact(&mut buffer).unwrap();
+        values.push(structures::dbtype::deserialize_value(&buffer, record_structure).unwrap());
+    }
+
+    Ok(values)
+}
+
+/// Finds the field the filter is applied to and the values it's compared with, brought to
+/// the type of the field. That's the only value for filters that don't take a list. Values
+/// compared with json documents are left as they are
+fn filter_operands(
+    metadata: &TableMetadata,
+    filter: &FilterOption,
+) -> Result<(usize, Vec<Type>), String> {
+    let name = filter.column();
+    let Some(field_idx) = metadata
+        .fields()
+        .iter()
+        .position(|field| field.name == name)
+    else {
+        return Err(format!("Field '{}' doesn't exist", name));
+    };
+    let field = &metadata.fields()[field_idx];
+    let operands = match filter.filter().takes_list() {
+        true => filter.values().to_vec(),
+        false => vec![filter.value().clone()],
+    };
+    if filter.json_path().is_empty() {
+        let is_string = matches!(field.type_, Type::Varchar(_, _) | Type::Text(_));
+        if filter.filter().is_substring() && !is_string {
+            return Err(format!(
+                "Filter {:?} cannot be applied to '{}' field that is not a string.",
+                filter.filter(),
+                name
+            ));
+        }
+        if matches!(field.type_, Type::Json(_)) && filter.filter().orders() {
+            return Err(format!(
+                "Json documents of '{}' field are not ordered, their values are compared by a json path.",
+                name
+            ));
+        }
+    }
+    match filter.json_path().is_empty() {
+        true => {
+            let operands = operands
+                .into_iter()
+                .map(|operand| field.prepare_operand(operand))
+                .collect::<Result<_, _>>()?;
+            Ok((field_idx, operands))
+        }
+        false if matches!(field.type_, Type::Json(_)) => Ok((field_idx, operands)),
+        false => Err(format!(
+            "Json path is given for '{}' field that is not a json.",
+            name
+        )),
+    }
+}
+
+/// Whether the value matches the filter with the operands of `filter_operands`
+fn value_matches(filter: Filter, value: &Type, operands: &[Type], collation: Collation) -> bool {
+    match filter.takes_list() {
+        true => filter.matches_list(value, operands, collation),
+        false => filter.matches(value, &operands[0], collation),
+    }
+}
+
+/// Keeps positions of records whose field at `field_idx` matches the filter with the
+/// operands
+fn linear_search(
+    data: &mut HashSet<DataPosition>,
+    table_path: &Path,
+    filter: &FilterOption,
+    (number_of_filtered_field, operands): (usize, &[Type]),
+    metadata: &TableMetadata,
+) -> Result<(), String> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let pages_dir = table_path.join(PAGES_DIRECTORY_NAME);
+    let mut data_vec: Vec<DataPosition> = data.clone().into_iter().collect();
+    data_vec.sort_by_key(|position| position.page);
+
+    let record_structure = metadata.record_structure();
+    let record_size = metadata.record_size();
+    let collation = metadata.fields()[number_of_filtered_field].collation;
 
     let mut current_page_num = data_vec[0].page;
     let page_path = pages_dir.join(current_page_num.to_string());
@@ -560,18 +1396,88 @@ fn linear_search(
             let page_path = pages_dir.join(current_page_num.to_string());
             page_content = fs::read(page_path).unwrap();
         }
-        let record = dbtype::deserialize_value(
+        let mut record = dbtype::deserialize_value(
             &page_content[(position.cell as usize)..(position.cell + record_size) as usize],
             &record_structure,
         )
         .unwrap();
-        if record[number_of_filtered_field] != filter.field().type_ {
+        let value = &mut record[number_of_filtered_field];
+        load_overflow_value(
+            table_path,
+            value,
+            &record_structure[number_of_filtered_field],
+        )?;
+        let matches = match filter.json_path().is_empty() {
+            true => value_matches(*filter.filter(), value, operands, collation),
+            false => json_path_matches(value, filter, operands)?,
+        };
+        if !matches {
             data.remove(&position);
         }
     }
     Ok(())
 }
 
+/// Applies the filter to the value at the json path of a document. Missing values and
+/// values of null documents are treated as nulls
+fn json_path_matches(
+    value: &Type,
+    filter: &FilterOption,
+    operands: &[Type],
+) -> Result<bool, String> {
+    let document = match value {
+        Type::Json(data) => Some(json::decode(data).map_err(String::from)?),
+        _ => None,
+    };
+    let found = document
+        .as_ref()
+        .and_then(|document| json::get(document, filter.json_path()));
+    let operands: Option<Vec<_>> = operands.iter().map(json::from_type).collect();
+    Ok(match (filter.filter(), found, operands) {
+        (Filter::HasKey, found, _) => found.is_some(),
+        (Filter::IsNull, found, _) => found.is_none_or(|found| found.is_null()),
+        (Filter::IsNotNull, found, _) => found.is_some_and(|found| !found.is_null()),
+        (_, Some(found), Some(operands)) if !found.is_null() => {
+            let matches = |filter, operand| json_value_matches(filter, found, operand);
+            match (filter.filter(), operands.as_slice()) {
+                (Filter::In, operands) => operands
+                    .iter()
+                    .any(|operand| matches(Filter::Equal, operand)),
+                (Filter::NotIn, operands) => !operands
+                    .iter()
+                    .any(|operand| matches(Filter::Equal, operand)),
+                (Filter::Between, [lower, upper]) => {
+                    matches(Filter::GreaterEq, lower) && matches(Filter::LessEq, upper)
+                }
+                (&filter, [operand]) => matches(filter, operand),
+                _ => false,
+            }
+        }
+        _ => false,
+    })
+}
+
+/// Compares a json value that isn't null with the operand of a filter that doesn't take a
+/// list
+fn json_value_matches(
+    filter: Filter,
+    found: &serde_json::Value,
+    operand: &serde_json::Value,
+) -> bool {
+    match (filter, found, operand) {
+        (Filter::Equal, found, operand) => json::equal(found, operand),
+        (Filter::NotEqual, found, operand) => !json::equal(found, operand),
+        (filter, serde_json::Value::String(found), serde_json::Value::String(operand))
+            if filter.is_substring() =>
+        {
+            filter.matches_str(found, operand)
+        }
+        (filter, found, operand) => {
+            json::compare(found, operand).is_some_and(|ordering| filter.accepts(ordering))
+        }
+    }
+}
+
 fn get_all_positions(
     table_path: &Path,
     metadata: &TableMetadata,
@@ -599,7 +1505,10 @@ fn get_all_positions(
     // work with corner values
     for page_num in 0..num_of_pages {
         begining = 0;
-        while free_spaces[free_space_idx].page == page_num {
+        while free_spaces
+            .get(free_space_idx)
+            .is_some_and(|free_space| free_space.page == page_num)
+        {
             positions.append(&mut generate_positions(
                 page_num,
                 begining,
@@ -619,7 +1528,11 @@ fn get_all_positions(
     Ok(positions)
 }
 
-fn delete_records_by_position(table_path: &Path, positions: &[DataPosition]) -> Result<(), String> {
+fn delete_records_by_position(
+    table_path: &Path,
+    positions: &[DataPosition],
+    record_size: u16,
+) -> Result<(), String> {
     // TODO: deleting page when it depletes (rename last page and update number of pages)
     if positions.is_empty() {
         return Ok(());
@@ -633,7 +1546,9 @@ fn delete_records_by_position(table_path: &Path, positions: &[DataPosition]) ->
         &positions
             .iter()
             .map(|position| {
-                FreeSpace::new(position.page, position.cell, position.cell + 1).unwrap()
+                // Free spaces are measured in records while positions are measured in bytes
+                let cell = position.cell / record_size;
+                FreeSpace::new(position.page, cell, cell + 1).unwrap()
             })
             .collect::<Vec<FreeSpace>>(),
     );
@@ -664,79 +1579,379 @@ fn delete_records_by_position(table_path: &Path, positions: &[DataPosition]) ->
     Ok(())
 }
 
-/// Changes records by given positions and returns range of old values to use for indexing
-fn change_records_by_position(
+/// Removes records at the positions from the table together with their keys and indexes
+fn remove_records(table_path: &Path, record_positions: Vec<DataPosition>) -> Result<(), String> {
+    let indexes_dir = table_path.join(INDEXES_DIRECTORY_NAME);
+
+    let metadata = get_table_metadata(table_path)?;
+    let record_positions_set: HashSet<DataPosition> =
+        record_positions.clone().into_iter().collect();
+
+    // Delete indexes
+    let fields = metadata.fields();
+    let records_structure = metadata.record_structure();
+    let records = get_records_by_position(
+        table_path,
+        &record_positions,
+        &records_structure,
+        metadata.record_size(),
+    )?;
+    for record in &records {
+        delete_overflow_values(table_path, record)?;
+    }
+
+    for &index_idx in metadata.indexes_idx() {
+        let index_idx = index_idx as usize;
+        let collation = fields[index_idx].collation;
+        let values_range: BTreeSet<Type> = records
+            .iter()
+            .map(|record| collation.key(&record[index_idx]))
+            .collect();
+        let index_path = indexes_dir.join(&fields[index_idx].name);
+        let mut index = read_index(&index_path)?;
+        for value in values_range {
+            if let Some(positions) = index.get_mut(&value) {
+                positions.retain(|position| !record_positions_set.contains(position));
+            }
+        }
+        write_index(&index_path, &index)?;
+    }
+    for constraint in key_constraints(table_path, &metadata) {
+        let mut index = read_key_index(&constraint.index_path)?;
+        for key in records.iter().filter_map(|record| constraint.key(record)) {
+            index.remove(&key);
+        }
+        write_key_index(&constraint.index_path, &index)?;
+    }
+    delete_records_by_position(table_path, &record_positions, metadata.record_size())
+}
+
+/// Sets values of the fields of records at the positions, keeping constraints, indexes and
+/// generated fields of the table up to date
+fn change_records_at(
     table_path: &Path,
     metadata: &TableMetadata,
-    mut positions: Vec<DataPosition>,
-    values: Vec<(usize, Type)>,
-    old_values_positions: Vec<usize>,
-) -> Result<Vec<(HashSet<DataPosition>, BTreeSet<Type>)>, String> {
-    if positions.is_empty() {
-        return Ok(Vec::new());
-    }
+    records_positions: Vec<DataPosition>,
+    index_value: Vec<(usize, Type)>,
+) -> Result<(), Error> {
+    let changes = prepare_changes(table_path, metadata, records_positions, index_value)?;
+    write_changes(table_path, metadata, changes)
+}
 
-    let mut old_values: Vec<(HashSet<DataPosition>, BTreeSet<Type>)> =
-        vec![(HashSet::new(), BTreeSet::new()); old_values_positions.len()];
-    let page_dir = table_path.join(PAGES_DIRECTORY_NAME);
-    let record_size = metadata.record_size() as usize;
-    let record_structure: Vec<Type> = metadata
-        .fields()
+/// Builds new records at the positions and checks them against the constraints of the
+/// table, nothing is written yet
+fn prepare_changes(
+    table_path: &Path,
+    metadata: &TableMetadata,
+    mut records_positions: Vec<DataPosition>,
+    index_value: Vec<(usize, Type)>,
+) -> Result<RecordChanges, Error> {
+    let fields = metadata.fields();
+    let record_structure = metadata.record_structure();
+    records_positions.sort_by_key(|position| (position.page, position.cell));
+
+    // Generated fields that use the changed ones are computed again
+    let mut changed_fields: Vec<usize> = index_value.iter().map(|(idx, _)| *idx).collect();
+    let generated: Vec<(usize, Vec<u16>)> = fields
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, field)| Some((idx, field.generated.as_ref()?.fields())))
+        .filter(|(_, used)| {
+            used.iter()
+                .any(|&field| changed_fields.contains(&(field as usize)))
+        })
+        .collect();
+    let used_fields: BTreeSet<usize> = generated
         .iter()
-        .map(|field| field.type_.clone())
+        .flat_map(|(_, used)| used.iter().map(|&field| field as usize))
         .collect();
-    let update_record_unchecked = |record: &mut Vec<Type>| {
-        for (idx, value) in &values {
+    changed_fields.extend(generated.iter().map(|(idx, _)| *idx));
+
+    let old_records = get_records_by_position(
+        table_path,
+        &records_positions,
+        &record_structure,
+        metadata.record_size(),
+    )?;
+    let mut new_records = Vec::with_capacity(old_records.len());
+    for record in &old_records {
+        let mut record = record.clone();
+        for (idx, value) in &index_value {
             record[*idx] = value.clone();
         }
-    };
-    let mut record_old_values = |record: &[Type], position: &DataPosition| {
-        for (old_values_idx, record_idx) in old_values_positions.iter().enumerate() {
-            if let Some((positions, values)) = old_values.get_mut(old_values_idx) {
-                positions.insert(position.clone());
-                values.insert(record[*record_idx].clone());
+        if !generated.is_empty() {
+            // Long values the expressions use live in overflow pages
+            let mut loaded = record.clone();
+            for &idx in &used_fields {
+                load_overflow_value(table_path, &mut loaded[idx], &record_structure[idx])?;
+            }
+            for (idx, _) in &generated {
+                record[*idx] = fields[*idx].compute_value(&loaded)?;
             }
         }
-    };
-    let mut buffer = vec![0u8; record_size];
+        new_records.push(record);
+    }
 
-    positions.sort_by_key(|position| (position.page, position.cell));
+    // Check that changed records keep unique fields unique, reference existing records and
+    // meet the checks
+    let key_constraints: Vec<KeyConstraint> = key_constraints(table_path, metadata)
+        .into_iter()
+        .filter(|constraint| constraint.uses_any(&changed_fields))
+        .collect();
+    let foreign_keys: Vec<&ForeignKey> = metadata
+        .constraints()
+        .foreign_keys
+        .iter()
+        .filter(|foreign_key| {
+            foreign_key
+                .fields
+                .iter()
+                .any(|&field| changed_fields.contains(&(field as usize)))
+        })
+        .collect();
+    let checks: Vec<&CheckConstraint> = metadata
+        .constraints()
+        .checks
+        .iter()
+        .filter(|check| {
+            check
+                .fields()
+                .iter()
+                .any(|&field| changed_fields.contains(&(field as usize)))
+        })
+        .collect();
+    check_references(table_path, &foreign_keys, &new_records, &[])?;
+    check_conditions(metadata, &checks, &new_records)?;
+    let changed_positions: HashSet<DataPosition> = records_positions.iter().cloned().collect();
+    for constraint in &key_constraints {
+        let index = read_key_index(&constraint.index_path)?;
+        let new_keys: Vec<_> = new_records
+            .iter()
+            .map(|record| constraint.key(record))
+            .collect();
+        check_new_keys(constraint, &index, &new_keys, &changed_positions)?;
+    }
+    Ok(RecordChanges {
+        positions: records_positions,
+        old_records,
+        new_records,
+        changed_fields,
+    })
+}
 
-    let mut page_num = positions[0].page;
-    let page_path = page_dir.join(page_num.to_string());
-    let mut page_file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(page_path)
+/// Writes records prepared by `prepare_changes` and updates key indexes and indexes of the
+/// changed fields. Indexes are read at this point, so the table may change in between
+fn write_changes(
+    table_path: &Path,
+    metadata: &TableMetadata,
+    changes: RecordChanges,
+) -> Result<(), Error> {
+    let RecordChanges {
+        positions: records_positions,
+        old_records,
+        mut new_records,
+        changed_fields,
+    } = changes;
+    if records_positions.is_empty() {
+        return Ok(());
+    }
+    let indexes_dir = table_path.join(INDEXES_DIRECTORY_NAME);
+    let pages_dir = table_path.join(PAGES_DIRECTORY_NAME);
+    let fields = metadata.fields();
+    let record_structure = metadata.record_structure();
+    let changed_positions: HashSet<DataPosition> = records_positions.iter().cloned().collect();
+    let key_constraints: Vec<KeyConstraint> = key_constraints(table_path, metadata)
+        .into_iter()
+        .filter(|constraint| constraint.uses_any(&changed_fields))
+        .collect();
+    let mut key_indexes = Vec::with_capacity(key_constraints.len());
+    for constraint in &key_constraints {
+        let mut index = read_key_index(&constraint.index_path)?;
+        let new_keys: Vec<_> = new_records
+            .iter()
+            .map(|record| constraint.key(record))
+            .collect();
+        for key in old_records
+            .iter()
+            .filter_map(|record| constraint.key(record))
+        {
+            index.remove(&key);
+        }
+        key_indexes.push((index, new_keys));
+    }
+
+    // Writing records
+    let mut page: Option<(u64, File)> = None;
+    for ((position, old_record), new_record) in records_positions
+        .iter()
+        .zip(&old_records)
+        .zip(&mut new_records)
+    {
+        for &idx in &changed_fields {
+            delete_overflow_values(table_path, &old_record[idx..=idx])?;
+        }
+        store_overflow_values(table_path, new_record)?;
+        if page.as_ref().is_none_or(|(page, _)| *page != position.page) {
+            let page_path = pages_dir.join(position.page.to_string());
+            let file = match OpenOptions::new().write(true).open(&page_path) {
+                Ok(file) => file,
+                Err(e) => {
+                    return Err(Error::Other(format!(
+                        "Error while opening page {}: {}",
+                        page_path.display(),
+                        e
+                    )));
+                }
+            };
+            page = Some((position.page, file));
+        }
+        let (_, file) = page.as_mut().unwrap();
+        file.seek(SeekFrom::Start(position.cell as u64)).unwrap();
+        file.write_all(&structures::dbtype::serialize_values(
+            new_record,
+            &record_structure,
+        ))
         .unwrap();
-    for position in positions {
-        if position.page != page_num {
-            page_num = position.page;
-            let page_path = page_dir.join(page_num.to_string());
-            page_file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .open(page_path)
-                .unwrap();
-        }
-        page_file
-            .seek(SeekFrom::Start(position.cell as u64))
-            .unwrap();
-        page_file.read_exact(&mut buffer).unwrap();
+    }
 
-        let mut record = structures::dbtype::deserialize_value(&buffer, &record_structure)?;
+    for (constraint, (mut index, new_keys)) in key_constraints.iter().zip(key_indexes) {
+        for (key, position) in new_keys.into_iter().zip(&records_positions) {
+            if let Some(key) = key {
+                index.insert(key, position.clone());
+            }
+        }
+        write_key_index(&constraint.index_path, &index)?;
+    }
 
-        record_old_values(&record, &position);
-        update_record_unchecked(&mut record);
+    // Update indexes
+    for &field_idx in metadata.indexes_idx() {
+        let field_idx = field_idx as usize;
+        if !changed_fields.contains(&field_idx) {
+            continue;
+        }
+        let index_path = indexes_dir.join(&fields[field_idx].name);
+        let mut index = read_index(&index_path)?;
+        let collation = fields[field_idx].collation;
+        let old_values: BTreeSet<Type> = old_records
+            .iter()
+            .map(|record| collation.key(&record[field_idx]))
+            .collect();
+        for value in old_values {
+            if let Some(indexed_positions) = index.get_mut(&value) {
+                indexed_positions.retain(|pos| !changed_positions.contains(pos));
+            }
+        }
+        for (position, record) in records_positions.iter().zip(&new_records) {
+            index
+                .entry(collation.key(&record[field_idx]))
+                .or_insert_with(Vec::new)
+                .push(position.clone());
+        }
+        write_index(&index_path, &index)?;
+    }
 
-        page_file
-            .seek(SeekFrom::Start(position.cell as u64))
-            .unwrap();
-        page_file
-            .write_all(&structures::dbtype::serialize_values(&record))
-            .unwrap();
+    Ok(())
+}
+
+/// Moves texts, blobs and jsons that don't fit in the record to overflow pages and replaces
+/// them with references to these pages
+fn store_overflow_values(table_path: &Path, record: &mut [Type]) -> Result<(), String> {
+    if !record.iter().any(|value| value.needs_overflow()) {
+        return Ok(());
+    }
+    let overflow_dir = table_path
+        .join(PAGES_DIRECTORY_NAME)
+        .join(OVERFLOW_DIRECTORY_NAME);
+    if let Err(e) = fs::create_dir_all(&overflow_dir) {
+        return Err(format!("Error while creating overflow directory: {}", e));
+    }
+    let _guard = OVERFLOW_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut next_id = match fs::read(overflow_dir.join(NUMBER_OF_PAGES_FILE_NAME)) {
+        Ok(data) => match data.try_into() {
+            Ok(data) => u64::from_le_bytes(data),
+            Err(_) => return Err(String::from("Number of overflow pages is corrupted.")),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => {
+            return Err(format!(
+                "Error while reading number of overflow pages: {}",
+                e
+            ));
+        }
+    };
+    for value in record.iter_mut().filter(|value| value.needs_overflow()) {
+        let data = match value {
+            Type::Text(var) => var.as_bytes(),
+            Type::Blob(var) | Type::Json(var) => var.as_slice(),
+            _ => unreachable!(),
+        };
+        let len = match u32::try_from(data.len()) {
+            Ok(len) => len,
+            Err(_) => return Err(format!("Value of {} bytes is too big.", data.len())),
+        };
+        if let Err(e) = fs::write(overflow_dir.join(next_id.to_string()), data) {
+            return Err(format!("Error while writing overflow page: {}", e));
+        }
+        *value = Type::Overflow(len, next_id);
+        next_id += 1;
+    }
+    match fs::write(
+        overflow_dir.join(NUMBER_OF_PAGES_FILE_NAME),
+        next_id.to_le_bytes(),
+    ) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!(
+            "Error while writing number of overflow pages: {}",
+            e
+        )),
+    }
+}
+
+/// Replaces reference to an overflow page with the value stored there
+fn load_overflow_value(table_path: &Path, value: &mut Type, type_: &Type) -> Result<(), String> {
+    let Type::Overflow(_, id) = value else {
+        return Ok(());
+    };
+    let page_path = table_path
+        .join(PAGES_DIRECTORY_NAME)
+        .join(OVERFLOW_DIRECTORY_NAME)
+        .join(id.to_string());
+    let data = match fs::read(&page_path) {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(format!(
+                "Error while reading overflow page {}: {}",
+                page_path.display(),
+                e
+            ));
+        }
+    };
+    *value = match type_ {
+        Type::Text(_) => match String::from_utf8(data) {
+            Ok(var) => Type::Text(var),
+            Err(_) => return Err(String::from("Text contains invalid UTF-8.")),
+        },
+        Type::Json(_) => Type::Json(data),
+        _ => Type::Blob(data),
+    };
+    Ok(())
+}
+
+fn delete_overflow_values(table_path: &Path, values: &[Type]) -> Result<(), String> {
+    let overflow_dir = table_path
+        .join(PAGES_DIRECTORY_NAME)
+        .join(OVERFLOW_DIRECTORY_NAME);
+    for value in values {
+        if let Type::Overflow(_, id) = value
+            && let Err(e) = fs::remove_file(overflow_dir.join(id.to_string()))
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            return Err(format!("Error while deleting overflow page {}: {}", id, e));
+        }
     }
-    Ok(old_values)
+    Ok(())
 }
 
 fn read_number_of_pages(database_path: &Path) -> Result<u64, &'static str> {
@@ -751,6 +1966,66 @@ fn write_number_of_pages(database_path: &Path, value: u64) -> Result<(), &'stati
     Ok(())
 }
 
+fn read_sequence(sequence_path: &Path) -> Result<i128, String> {
+    match fs::read(sequence_path) {
+        Ok(data) => match data.try_into() {
+            Ok(data) => Ok(i128::from_le_bytes(data)),
+            Err(_) => Err(format!(
+                "Sequence at {} is corrupted.",
+                sequence_path.display()
+            )),
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(1),
+        Err(e) => Err(format!("Error while reading sequence: {}", e)),
+    }
+}
+
+fn write_sequence(sequence_path: &Path, next_value: i128) -> Result<(), String> {
+    if let Some(sequences_dir) = sequence_path.parent()
+        && let Err(e) = fs::create_dir_all(sequences_dir)
+    {
+        return Err(format!("Error while creating sequences directory: {}", e));
+    }
+    match fs::write(sequence_path, next_value.to_le_bytes()) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("Error while writing sequence: {}", e)),
+    }
+}
+
+fn write_key_index(index_path: &Path, index_data: &KeyIndex) -> Result<(), String> {
+    let config = bincode::config::standard().with_little_endian();
+    let data = match bincode::encode_to_vec(index_data, config) {
+        Ok(data) => data,
+        Err(e) => return Err(format!("Error while encoding key index: {}", e)),
+    };
+    match fs::write(index_path, data) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!(
+            "Key index file at {} is not accessable: {}",
+            index_path.display(),
+            e
+        )),
+    }
+}
+
+fn read_key_index(index_path: &Path) -> Result<KeyIndex, String> {
+    let config = bincode::config::standard().with_little_endian();
+    let data = match fs::read(index_path) {
+        Ok(data) => data,
+        Err(e) => {
+            return Err(format!(
+                "Key index file at {} is not accessable: {}",
+                index_path.display(),
+                e
+            ));
+        }
+    };
+    match bincode::decode_from_slice(&data, config) {
+        Ok((index, _)) => Ok(index),
+        Err(e) => Err(format!("Error while decoding key index: {}", e)),
+    }
+}
+
 fn write_index(
     index_path: &Path,
     index_data: &BTreeMap<Type, Vec<DataPosition>>,
@@ -798,5 +2073,859 @@ fn dir_is_table(table_path: &Path) -> bool {
     let descriptor = table_dir.join(DESCRIPTION_FILE_NAME);
     let free_space = table_dir.join(FREE_SPACE_FILE_NAME);
     let pages = table_dir.join(PAGES_DIRECTORY_NAME);
-    table_dir.exists() && descriptor.exists() && free_space.exists()
+    table_dir.exists() && descriptor.exists() && free_space.exists() && pages.exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(name: &str, type_: Type, nullable: bool) -> Field {
+        Field {
+            name: String::from(name),
+            type_,
+            nullable,
+            truncate: false,
+            auto_increment: false,
+            generate_uuid: None,
+            default: None,
+            generated: None,
+            collation: Collation::Binary,
+        }
+    }
+
+    /// Empty directory for a database in the temp directory
+    fn database(name: &str) -> PathBuf {
+        let database_path =
+            std::env::temp_dir().join(format!("db-engine-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&database_path);
+        database_path
+    }
+
+    fn table(
+        database_path: &Path,
+        name: &str,
+        fields: Vec<Field>,
+        indexes: Vec<u16>,
+        constraints: Constraints,
+    ) -> PathBuf {
+        let metadata = TableMetadata::new(fields, vec![0], indexes, constraints);
+        create_table(database_path, name, metadata.unwrap()).unwrap();
+        database_path.join(name)
+    }
+
+    fn equal(column: &str, value: Type) -> FilterExpression {
+        let option = FilterOption::new(String::from(column), Filter::Equal, vec![], value, vec![]);
+        FilterExpression::Filter(option.unwrap())
+    }
+
+    /// Whether the operation failed because of the constraint
+    fn violated<T>(result: Result<T, Error>, constraint: &str) -> bool {
+        matches!(result, Err(Error::ConstraintViolation(name, _)) if name == constraint)
+    }
+
+    /// All records of the table sorted by their values
+    fn records(table_path: &Path) -> Vec<Vec<Type>> {
+        let mut records = get_records(table_path, &FilterExpression::all()).unwrap();
+        records.sort();
+        records
+    }
+
+    /// Table of orders with their status and total in a fresh database, totals of every
+    /// fourth order are null
+    fn orders_table(database_name: &str, indexes: Vec<u16>) -> PathBuf {
+        let database_path = database(database_name);
+        let fields = vec![
+            field("id", Type::I32(0), false),
+            field("status", Type::Varchar(8, String::new()), false),
+            field("total", Type::I32(0), true),
+        ];
+        let metadata = TableMetadata::new(fields, vec![0], indexes, Constraints::default());
+        create_table(&database_path, "orders", metadata.unwrap()).unwrap();
+        let table_path = database_path.join("orders");
+        let records = (0..9)
+            .map(|id| {
+                let status = ["a", "b", "c"][id as usize % 3];
+                vec![
+                    Some(Type::I32(id)),
+                    Some(Type::Varchar(8, String::from(status))),
+                    Some(if id % 4 == 3 {
+                        Type::Null
+                    } else {
+                        Type::I32(id * 10)
+                    }),
+                ]
+            })
+            .collect();
+        add_records(&table_path, &[], records).unwrap();
+        table_path
+    }
+
+    fn ids(table_path: &Path, filter: &FilterExpression) -> Vec<i32> {
+        let mut ids: Vec<i32> = get_records(table_path, filter)
+            .unwrap()
+            .iter()
+            .map(|record| match record[0] {
+                Type::I32(id) => id,
+                _ => unreachable!(),
+            })
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn filter_expressions() {
+        let filter = |column: &str, filter, value| {
+            let option = FilterOption::new(String::from(column), filter, vec![], value, vec![]);
+            FilterExpression::Filter(option.unwrap())
+        };
+        let status = |status: &str| {
+            filter(
+                "status",
+                Filter::Equal,
+                Type::Varchar(8, String::from(status)),
+            )
+        };
+        let expensive = || filter("total", Filter::Greater, Type::I32(30));
+        let not = |expression| FilterExpression::Not(Box::new(expression));
+        let all: Vec<i32> = (0..9).collect();
+        let list = |column: &str, filter, values: Vec<Type>| {
+            let option =
+                FilterOption::new(String::from(column), filter, vec![], Type::Null, values);
+            FilterExpression::Filter(option.unwrap())
+        };
+        let statuses = |filter, statuses: &[&str]| {
+            let values = statuses
+                .iter()
+                .map(|&status| Type::Varchar(8, String::from(status)))
+                .collect();
+            list("status", filter, values)
+        };
+        let totals = |filter, totals: &[i32]| {
+            list(
+                "total",
+                filter,
+                totals.iter().map(|&total| Type::I32(total)).collect(),
+            )
+        };
+
+        for (database, indexes) in [("expressions_indexed", vec![1, 2]), ("expressions", vec![])] {
+            let orders = orders_table(database, indexes);
+            let either = FilterExpression::Or(vec![status("a"), status("b")]);
+            assert_eq!(ids(&orders, &either), [0, 1, 3, 4, 6, 7]);
+            // Orders with null totals aren't expensive, so they are found by 'not'
+            assert_eq!(ids(&orders, &not(expensive())), [0, 1, 2, 3, 7]);
+            let cheap_a = FilterExpression::And(vec![status("a"), not(expensive())]);
+            assert_eq!(ids(&orders, &cheap_a), [0, 3]);
+            assert_eq!(ids(&orders, &not(cheap_a)), [1, 2, 4, 5, 6, 7, 8]);
+
+            assert_eq!(ids(&orders, &FilterExpression::And(vec![])), all);
+            assert!(ids(&orders, &FilterExpression::Or(vec![])).is_empty());
+            assert_eq!(ids(&orders, &not(FilterExpression::Or(vec![]))), all);
+
+            // Lists of values are looked up one by one in the index, nulls match none of
+            // the filters
+            assert_eq!(
+                ids(&orders, &statuses(Filter::In, &["a", "c"])),
+                [0, 2, 3, 5, 6, 8]
+            );
+            assert_eq!(
+                ids(&orders, &statuses(Filter::NotIn, &["a", "c"])),
+                [1, 4, 7]
+            );
+            assert_eq!(ids(&orders, &totals(Filter::In, &[10, 30, 50, 55])), [1, 5]);
+            assert_eq!(
+                ids(&orders, &totals(Filter::NotIn, &[10, 50])),
+                [0, 2, 4, 6, 8]
+            );
+            let in_list = totals(Filter::In, &[10, 50]);
+            assert_eq!(ids(&orders, &not(in_list)), [0, 2, 3, 4, 6, 7, 8]);
+            let not_a = filter(
+                "status",
+                Filter::NotEqual,
+                Type::Varchar(8, String::from("a")),
+            );
+            assert_eq!(ids(&orders, &not_a), [1, 2, 4, 5, 7, 8]);
+            let not_twenty = filter("total", Filter::NotEqual, Type::I32(20));
+            assert_eq!(ids(&orders, &not_twenty), [0, 1, 4, 5, 6, 8]);
+
+            // Bounds of 'between' are walked in the index together with other ranges
+            assert_eq!(ids(&orders, &totals(Filter::Between, &[20, 50])), [2, 4, 5]);
+            let narrowed =
+                FilterExpression::And(vec![totals(Filter::Between, &[20, 60]), expensive()]);
+            assert_eq!(ids(&orders, &narrowed), [4, 5, 6]);
+            assert!(ids(&orders, &totals(Filter::Between, &[50, 20])).is_empty());
+            assert!(ids(&orders, &totals(Filter::Between, &[45, 45])).is_empty());
+            assert_eq!(ids(&orders, &totals(Filter::Between, &[40, 40])), [4]);
+            fs::remove_dir_all(orders.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn index_ranges() {
+        let (four, five, six, seven) = (Type::I32(4), Type::I32(5), Type::I32(6), Type::I32(7));
+        let index: BTreeMap<Type, Vec<DataPosition>> = [&Type::Null, &four, &five, &six, &seven]
+            .into_iter()
+            .map(|key| (key.clone(), Vec::new()))
+            .collect();
+        let keys = |filters: &[(Filter, Type)]| -> Option<Vec<Type>> {
+            let range = index_range(filters)?;
+            Some(index.range(range).map(|(key, _)| key.clone()).collect())
+        };
+
+        // The tighter one of '>' and '>=' on the same operand is taken in any order
+        let greater = [
+            (Filter::GreaterEq, five.clone()),
+            (Filter::Greater, five.clone()),
+        ];
+        let expected = Some(vec![six.clone(), seven.clone()]);
+        assert_eq!(keys(&greater), expected);
+        assert_eq!(keys(&[greater[1].clone(), greater[0].clone()]), expected);
+        assert_eq!(
+            keys(&greater[..1]),
+            Some(vec![five.clone(), six.clone(), seven.clone()])
+        );
+
+        // Ranges with nothing between equal bounds are empty rather than panicking
+        assert_eq!(
+            keys(&[
+                (Filter::Greater, five.clone()),
+                (Filter::Less, five.clone())
+            ]),
+            None
+        );
+        assert_eq!(
+            keys(&[
+                (Filter::GreaterEq, five.clone()),
+                (Filter::Less, five.clone())
+            ]),
+            None
+        );
+        assert_eq!(
+            keys(&[
+                (Filter::Greater, seven.clone()),
+                (Filter::Less, five.clone())
+            ]),
+            None
+        );
+        assert_eq!(
+            keys(&[
+                (Filter::GreaterEq, five.clone()),
+                (Filter::LessEq, five.clone())
+            ]),
+            Some(vec![five.clone()])
+        );
+        // Bounds of 'between' the wrong way round
+        assert_eq!(
+            keys(&[
+                (Filter::GreaterEq, six.clone()),
+                (Filter::LessEq, five.clone())
+            ]),
+            None
+        );
+
+        // Nulls are left out when there is only an upper bound
+        assert_eq!(
+            keys(&[(Filter::Less, five.clone())]),
+            Some(vec![four.clone()])
+        );
+        assert_eq!(
+            keys(&[
+                (Filter::LessEq, seven.clone()),
+                (Filter::LessEq, five.clone())
+            ]),
+            Some(vec![four.clone(), five.clone()])
+        );
+    }
+
+    #[test]
+    fn set_null_is_checked_before_deleting() {
+        let database_path = database("set_null_checked");
+        let customers = table(
+            &database_path,
+            "customers",
+            vec![field("id", Type::I32(0), false)],
+            vec![],
+            Constraints::default(),
+        );
+        let set_null = |name: &str| ForeignKey {
+            name: String::from(name),
+            fields: vec![1],
+            table: String::from("customers"),
+            on_delete: ReferentialAction::SetNull,
+        };
+        let checked = table(
+            &database_path,
+            "checked",
+            vec![
+                field("id", Type::I32(0), false),
+                field("customer", Type::I32(0), true),
+            ],
+            vec![1],
+            Constraints {
+                foreign_keys: vec![set_null("checked_customer")],
+                checks: vec![CheckConstraint {
+                    name: String::from("customer_required"),
+                    field: 1,
+                    filter: Filter::IsNotNull,
+                    operand: CheckOperand::Value(Type::Null),
+                }],
+                ..Constraints::default()
+            },
+        );
+        let mut code = field("code", Type::I32(0), false);
+        code.generated = Some(Expression::Add(
+            Box::new(Expression::Field(1)),
+            Box::new(Expression::Value(Type::I32(100))),
+        ));
+        let generated = table(
+            &database_path,
+            "generated",
+            vec![
+                field("id", Type::I32(0), false),
+                field("customer", Type::I32(0), true),
+                code,
+            ],
+            vec![],
+            Constraints {
+                foreign_keys: vec![set_null("generated_customer")],
+                ..Constraints::default()
+            },
+        );
+        let customer = |id| vec![Some(Type::I32(id))];
+        add_records(&customers, &[], vec![customer(1), customer(2)]).unwrap();
+        let order = vec![Some(Type::I32(1)), Some(Type::I32(1))];
+        add_records(&checked, &[], vec![order.clone()]).unwrap();
+        let order = vec![Some(Type::I32(1)), Some(Type::I32(2)), None];
+        add_records(&generated, &[], vec![order]).unwrap();
+
+        assert!(violated(
+            delete_records(&customers, &equal("id", Type::I32(1))),
+            "customer_required"
+        ));
+        assert!(delete_records(&customers, &equal("id", Type::I32(2))).is_err());
+        assert_eq!(
+            records(&customers),
+            [vec![Type::I32(1)], vec![Type::I32(2)]]
+        );
+        assert_eq!(records(&checked), [vec![Type::I32(1), Type::I32(1)]]);
+        assert_eq!(
+            records(&generated),
+            [vec![Type::I32(1), Type::I32(2), Type::I32(102)]]
+        );
+        fs::remove_dir_all(&database_path).unwrap();
+    }
+
+    #[test]
+    fn foreign_keys() {
+        let reference = |value: Option<i32>| value.map_or(Type::Null, Type::I32);
+        // Referencing fields follow the primary keys
+        let key = |name: &str, table: &str, on_delete| ForeignKey {
+            name: String::from(name),
+            fields: vec![1],
+            table: String::from(table),
+            on_delete,
+        };
+        let references = |foreign_key: ForeignKey| Constraints {
+            foreign_keys: vec![foreign_key],
+            ..Constraints::default()
+        };
+        // References are found by the index of the referencing field or by a scan
+        for (database_name, indexes) in
+            [("foreign_keys_indexed", vec![1]), ("foreign_keys", vec![])]
+        {
+            let database_path = database(database_name);
+            let child = |name: &str, reference: &str, nullable, foreign_key| {
+                let fields = vec![
+                    field("id", Type::I32(0), false),
+                    field(reference, Type::I32(0), nullable),
+                ];
+                table(
+                    &database_path,
+                    name,
+                    fields,
+                    indexes.clone(),
+                    references(foreign_key),
+                )
+            };
+            let fields = vec![field("id", Type::I32(0), false)];
+            let no_constraints = Constraints::default();
+            let customers = table(&database_path, "customers", fields, vec![], no_constraints);
+            let cascade = ReferentialAction::Cascade;
+            let orders_customer = key("orders_customer", "customers", cascade);
+            let orders = child("orders", "customer", true, orders_customer);
+            let items_order = key("items_order", "orders", cascade);
+            let items = child("items", "order", false, items_order);
+            let restrict = ReferentialAction::Restrict;
+            let notes_customer = key("notes_customer", "customers", restrict);
+            let notes = child("notes", "customer", false, notes_customer);
+            let set_null = ReferentialAction::SetNull;
+            let reviews_customer = key("reviews_customer", "customers", set_null);
+            let reviews = child("reviews", "customer", true, reviews_customer);
+            let managers = key("employees_manager", "employees", cascade);
+            let employees = child("employees", "manager", true, managers);
+
+            let add = |table_path: &Path, records: &[(i32, Option<i32>)]| {
+                let records = records
+                    .iter()
+                    .map(|&(id, value)| vec![Some(Type::I32(id)), Some(reference(value))])
+                    .collect();
+                add_records(table_path, &[], records).map(|_| ())
+            };
+            let stored = |table_path: &Path| -> Vec<(i32, Option<i32>)> {
+                records(table_path)
+                    .into_iter()
+                    .map(|record| match record[..] {
+                        [Type::I32(id), Type::I32(reference)] => (id, Some(reference)),
+                        [Type::I32(id), Type::Null] => (id, None),
+                        _ => unreachable!(),
+                    })
+                    .collect()
+            };
+            let customer = |id| vec![Some(Type::I32(id))];
+            add_records(&customers, &[], vec![customer(1), customer(2), customer(3)]).unwrap();
+            add(
+                &orders,
+                &[(10, Some(1)), (11, Some(1)), (12, Some(2)), (13, None)],
+            )
+            .unwrap();
+            add(&items, &[(100, Some(10)), (101, Some(11)), (102, Some(12))]).unwrap();
+            add(&notes, &[(1000, Some(2))]).unwrap();
+            add(&reviews, &[(20, Some(1)), (21, Some(3))]).unwrap();
+            add(
+                &employees,
+                &[(1, None), (2, Some(1)), (3, Some(2)), (4, None)],
+            )
+            .unwrap();
+
+            // Records cannot reference missing ones, neither when added nor when changed
+            assert!(matches!(
+                add(&orders, &[(14, Some(9))]),
+                Err(Error::ConstraintViolation(name, _)) if name == "orders_customer"
+            ));
+            let change = |filter, value| {
+                let structure = vec![field("customer", Type::I32(0), true)];
+                change_records(&orders, &filter, structure, vec![value])
+            };
+            assert!(matches!(
+                change(equal("id", Type::I32(13)), Type::I32(9)),
+                Err(Error::ConstraintViolation(name, _)) if name == "orders_customer"
+            ));
+            change(equal("id", Type::I32(13)), Type::I32(3)).unwrap();
+            add(&orders, &[(14, None)]).unwrap();
+
+            // A restricting reference keeps records of all tables in place
+            assert!(matches!(
+                delete_records(&customers, &equal("id", Type::I32(2))),
+                Err(Error::ConstraintViolation(name, _)) if name == "notes_customer"
+            ));
+            assert_eq!(records(&customers).len(), 3);
+            assert_eq!(stored(&orders).len(), 5);
+            assert_eq!(stored(&items).len(), 3);
+
+            delete_records(&customers, &equal("id", Type::I32(1))).unwrap();
+            assert_eq!(stored(&orders), [(12, Some(2)), (13, Some(3)), (14, None)]);
+            assert_eq!(stored(&items), [(102, Some(12))]);
+            assert_eq!(stored(&reviews), [(20, None), (21, Some(3))]);
+
+            delete_records(&employees, &equal("id", Type::I32(1))).unwrap();
+            assert_eq!(stored(&employees), [(4, None)]);
+
+            // Clearing a table deletes its records one by one as far as references go
+            assert!(matches!(
+                clear_table(&customers),
+                Err(Error::ConstraintViolation(name, _)) if name == "notes_customer"
+            ));
+            assert_eq!(records(&customers).len(), 2);
+            delete_records(&notes, &FilterExpression::all()).unwrap();
+            clear_table(&customers).unwrap();
+            assert!(records(&customers).is_empty());
+            assert_eq!(stored(&orders), [(14, None)]);
+            assert!(stored(&items).is_empty());
+            assert_eq!(stored(&reviews), [(20, None), (21, None)]);
+
+            assert!(matches!(
+                delete_table(&customers),
+                Err(Error::ConstraintViolation(_, _))
+            ));
+            assert!(customers.exists());
+            // References to the table itself don't keep it
+            delete_table(&employees).unwrap();
+            for table_path in [&items, &orders, &notes, &reviews, &customers] {
+                delete_table(table_path).unwrap();
+            }
+            assert!(get_table_list(&database_path).unwrap().is_empty());
+            fs::remove_dir_all(&database_path).unwrap();
+        }
+    }
+
+    #[test]
+    fn unique_constraints() {
+        let database_path = database("unique_constraints");
+        let string = || Type::Varchar(16, String::new());
+        let fields = vec![
+            field("id", Type::I32(0), false),
+            field("email", string(), true),
+            field("city", string(), true),
+            field("room", Type::I32(0), true),
+        ];
+        let unique = |name: &str, fields| UniqueConstraint {
+            name: String::from(name),
+            fields,
+        };
+        let constraints = Constraints {
+            unique: vec![unique("email", vec![1]), unique("place", vec![2, 3])],
+            ..Constraints::default()
+        };
+        let people = table(&database_path, "people", fields, vec![], constraints);
+        let text = |value: &str| Type::Varchar(16, String::from(value));
+        let person = |id, email: Option<&str>, city: Option<&str>, room: Option<i32>| {
+            vec![
+                Some(Type::I32(id)),
+                Some(email.map_or(Type::Null, text)),
+                Some(city.map_or(Type::Null, text)),
+                Some(room.map_or(Type::Null, Type::I32)),
+            ]
+        };
+        let add = |records| add_records(&people, &[], records);
+        let change = |filter, value| {
+            let structure = vec![field("email", string(), true)];
+            change_records(&people, &filter, structure, vec![value])
+        };
+        let emails = || -> Vec<Type> {
+            records(&people)
+                .into_iter()
+                .map(|record| record[1].clone())
+                .collect()
+        };
+
+        add(vec![person(1, Some("a"), Some("x"), Some(1))]).unwrap();
+        assert!(violated(
+            add(vec![person(2, Some("a"), None, None)]),
+            "email"
+        ));
+        let batch = vec![
+            person(2, Some("b"), None, None),
+            person(3, Some("b"), None, None),
+        ];
+        assert!(violated(add(batch), "email"));
+        assert!(violated(
+            add(vec![person(1, Some("c"), None, None)]),
+            "primary_key"
+        ));
+        assert!(violated(
+            add(vec![person(2, None, Some("x"), Some(1))]),
+            "place"
+        ));
+        assert_eq!(emails(), [text("a")]);
+
+        // Keys with nulls never conflict
+        add(vec![
+            person(2, None, Some("x"), None),
+            person(3, None, Some("x"), None),
+            person(4, Some("d"), None, Some(1)),
+        ])
+        .unwrap();
+        change(FilterExpression::all(), Type::Null).unwrap();
+        // Records keep their own keys
+        change(equal("id", Type::I32(1)), text("a")).unwrap();
+        change(equal("id", Type::I32(1)), text("a")).unwrap();
+        let both = FilterExpression::Or(vec![equal("id", Type::I32(2)), equal("id", Type::I32(3))]);
+        assert!(violated(change(both, text("b")), "email"));
+        assert!(violated(
+            change(equal("id", Type::I32(2)), text("a")),
+            "email"
+        ));
+
+        // Keys of deleted and changed records are free again
+        delete_records(&people, &equal("id", Type::I32(1))).unwrap();
+        add(vec![person(5, Some("a"), Some("x"), Some(1))]).unwrap();
+        change(equal("id", Type::I32(5)), text("e")).unwrap();
+        add(vec![person(6, Some("a"), None, None)]).unwrap();
+        assert!(violated(
+            add(vec![person(7, Some("e"), None, None)]),
+            "email"
+        ));
+        let null = Type::Null;
+        assert_eq!(
+            emails(),
+            [null.clone(), null.clone(), null, text("e"), text("a")]
+        );
+        fs::remove_dir_all(&database_path).unwrap();
+    }
+
+    #[test]
+    fn generated_fields() {
+        let database_path = database("generated_fields");
+        let mut total = field("total", Type::I32(0), false);
+        total.generated = Some(Expression::Multiply(
+            Box::new(Expression::Field(1)),
+            Box::new(Expression::Field(2)),
+        ));
+        let fields = vec![
+            field("id", Type::I32(0), false),
+            field("price", Type::I32(0), false),
+            field("qty", Type::I32(0), false),
+            total,
+        ];
+        let lines = table(
+            &database_path,
+            "lines",
+            fields,
+            vec![3],
+            Constraints::default(),
+        );
+        let line = |id, price, qty| {
+            vec![
+                Some(Type::I32(id)),
+                Some(Type::I32(price)),
+                Some(Type::I32(qty)),
+                None,
+            ]
+        };
+        add_records(
+            &lines,
+            &[],
+            vec![line(1, 5, 4), line(2, 10, 2), line(3, 7, 1)],
+        )
+        .unwrap();
+        let stored = |id, price, qty, total| {
+            vec![
+                Type::I32(id),
+                Type::I32(price),
+                Type::I32(qty),
+                Type::I32(total),
+            ]
+        };
+        assert_eq!(
+            records(&lines),
+            [
+                stored(1, 5, 4, 20),
+                stored(2, 10, 2, 20),
+                stored(3, 7, 1, 7)
+            ]
+        );
+        let totals = |total| ids(&lines, &equal("total", Type::I32(total)));
+        assert_eq!(totals(20), [1, 2]);
+
+        let qty = vec![field("qty", Type::I32(0), false)];
+        change_records(&lines, &equal("id", Type::I32(2)), qty, vec![Type::I32(3)]).unwrap();
+        assert_eq!(
+            records(&lines),
+            [
+                stored(1, 5, 4, 20),
+                stored(2, 10, 3, 30),
+                stored(3, 7, 1, 7)
+            ]
+        );
+        assert_eq!(totals(20), [1]);
+        assert_eq!(totals(30), [2]);
+
+        // Generated values are only computed
+        let mut explicit = line(4, 1, 1);
+        explicit[3] = Some(Type::I32(1));
+        assert!(add_records(&lines, &[], vec![explicit]).is_err());
+        let total = vec![field("total", Type::I32(0), false)];
+        assert!(
+            change_records(&lines, &FilterExpression::all(), total, vec![Type::I32(1)]).is_err()
+        );
+        assert!(totals(1).is_empty());
+        assert_eq!(records(&lines).len(), 3);
+        fs::remove_dir_all(&database_path).unwrap();
+    }
+
+    #[test]
+    fn check_constraints() {
+        let database_path = database("check_constraints");
+        let fields = vec![
+            field("id", Type::I32(0), false),
+            field("price", Type::I32(0), true),
+            field("discount", Type::I32(0), true),
+            field("stock", Type::I32(0), false),
+        ];
+        let check = |name: &str, field, filter, operand| CheckConstraint {
+            name: String::from(name),
+            field,
+            filter,
+            operand,
+        };
+        let constraints = Constraints {
+            checks: vec![
+                check(
+                    "positive_price",
+                    1,
+                    Filter::Greater,
+                    CheckOperand::Value(Type::I32(0)),
+                ),
+                check(
+                    "discount_below_price",
+                    2,
+                    Filter::Less,
+                    CheckOperand::Field(1),
+                ),
+            ],
+            ..Constraints::default()
+        };
+        let products = table(&database_path, "products", fields, vec![], constraints);
+        let product = |id, price: Option<i32>, discount: Option<i32>| {
+            vec![
+                Some(Type::I32(id)),
+                Some(price.map_or(Type::Null, Type::I32)),
+                Some(discount.map_or(Type::Null, Type::I32)),
+                Some(Type::I32(0)),
+            ]
+        };
+        let add = |record| add_records(&products, &[], vec![record]);
+        let change = |name: &str, value| {
+            let structure = vec![field(name, Type::I32(0), true)];
+            change_records(
+                &products,
+                &equal("id", Type::I32(1)),
+                structure,
+                vec![value],
+            )
+        };
+
+        add(product(1, Some(10), Some(2))).unwrap();
+        assert!(violated(add(product(2, Some(0), None)), "positive_price"));
+        assert!(violated(
+            add(product(2, Some(10), Some(10))),
+            "discount_below_price"
+        ));
+        // Conditions on nulls hold
+        add(product(2, None, Some(10))).unwrap();
+
+        assert!(violated(change("price", Type::I32(-1)), "positive_price"));
+        assert!(violated(
+            change("price", Type::I32(2)),
+            "discount_below_price"
+        ));
+        assert!(violated(
+            change("discount", Type::I32(20)),
+            "discount_below_price"
+        ));
+        change("price", Type::I32(3)).unwrap();
+        // Records are checked only against conditions on the changed fields
+        let stock = vec![field("stock", Type::I32(0), false)];
+        change_records(
+            &products,
+            &FilterExpression::all(),
+            stock,
+            vec![Type::I32(5)],
+        )
+        .unwrap();
+        let stored = |id, price: Option<i32>, discount: Option<i32>| {
+            vec![
+                Type::I32(id),
+                price.map_or(Type::Null, Type::I32),
+                discount.map_or(Type::Null, Type::I32),
+                Type::I32(5),
+            ]
+        };
+        assert_eq!(
+            records(&products),
+            [stored(1, Some(3), Some(2)), stored(2, None, Some(10))]
+        );
+        fs::remove_dir_all(&database_path).unwrap();
+    }
+
+    #[test]
+    fn auto_increment() {
+        let database_path = database("auto_increment");
+        let mut id = field("id", Type::I64(0), false);
+        id.auto_increment = true;
+        let fields = vec![id, field("note", Type::I32(0), true)];
+        let tickets = table(
+            &database_path,
+            "tickets",
+            fields,
+            vec![],
+            Constraints::default(),
+        );
+        let ticket = |id: Option<i64>| vec![id.map(Type::I64), Some(Type::Null)];
+        let add = |ids: &[Option<i64>]| {
+            let records = ids.iter().map(|&id| ticket(id)).collect();
+            add_records(&tickets, &[], records).unwrap()
+        };
+        let keys =
+            |ids: &[i64]| -> Vec<Vec<Type>> { ids.iter().map(|&id| vec![Type::I64(id)]).collect() };
+
+        assert_eq!(add(&[None, None, None]), keys(&[1, 2, 3]));
+        assert_eq!(add(&[None, None]), keys(&[4, 5]));
+        // The sequence moves past explicit values and never goes back
+        assert_eq!(add(&[None, Some(10), None]), keys(&[11, 10, 12]));
+        assert_eq!(add(&[Some(7), None]), keys(&[7, 13]));
+        let columns = [String::from("note")];
+        let added = add_records(&tickets, &columns, vec![vec![Some(Type::I32(1))]]);
+        assert_eq!(added.unwrap(), keys(&[14]));
+        let stored: Vec<Vec<Type>> = records(&tickets)
+            .into_iter()
+            .map(|record| record[..1].to_vec())
+            .collect();
+        assert_eq!(stored, keys(&[1, 2, 3, 4, 5, 7, 10, 11, 12, 13, 14]));
+        fs::remove_dir_all(&database_path).unwrap();
+    }
+
+    #[test]
+    fn overflow_values() {
+        let database_path = database("overflow_values");
+        let fields = vec![
+            field("id", Type::I32(0), false),
+            field("body", Type::Text(String::new()), true),
+            field("data", Type::Blob(Vec::new()), true),
+        ];
+        let documents = table(
+            &database_path,
+            "documents",
+            fields,
+            vec![],
+            Constraints::default(),
+        );
+        let overflow_dir = documents
+            .join(PAGES_DIRECTORY_NAME)
+            .join(OVERFLOW_DIRECTORY_NAME);
+        let overflow_pages = || {
+            let mut pages: Vec<String> = fs::read_dir(&overflow_dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .filter(|name| name != NUMBER_OF_PAGES_FILE_NAME)
+                .collect();
+            pages.sort();
+            pages
+        };
+        let body = "a".repeat(PAGE_SIZE_BYTES as usize * 2);
+        let data: Vec<u8> = (0..PAGE_SIZE_BYTES as usize + 1).map(|i| i as u8).collect();
+        let document = vec![
+            Some(Type::I32(1)),
+            Some(Type::Text(body.clone())),
+            Some(Type::Blob(data.clone())),
+        ];
+        add_records(&documents, &[], vec![document]).unwrap();
+        assert_eq!(overflow_pages(), ["0", "1"]);
+        assert_eq!(
+            records(&documents),
+            [vec![
+                Type::I32(1),
+                Type::Text(body),
+                Type::Blob(data.clone())
+            ]]
+        );
+
+        let body = "b".repeat(PAGE_SIZE_BYTES as usize + 1);
+        let structure = vec![field("body", Type::Text(String::new()), true)];
+        let values = vec![Type::Text(body.clone())];
+        change_records(&documents, &FilterExpression::all(), structure, values).unwrap();
+        // The old value's page is freed, the blob keeps its page
+        assert_eq!(overflow_pages(), ["1", "2"]);
+        assert_eq!(
+            records(&documents),
+            [vec![Type::I32(1), Type::Text(body), Type::Blob(data)]]
+        );
+
+        delete_records(&documents, &FilterExpression::all()).unwrap();
+        assert!(overflow_pages().is_empty());
+        assert!(records(&documents).is_empty());
+        fs::remove_dir_all(&database_path).unwrap();
+    }
 }
@@ -1,14 +1,28 @@
+pub mod collation;
+pub mod constraints;
 pub mod dbtype;
+pub mod expression;
 pub mod field;
 pub mod filters;
 pub mod free_space;
+pub mod json;
 pub mod table_metadata;
 
 use bincode::Decode;
 use bincode::Encode;
+pub use collation::Collation;
+pub use constraints::CheckConstraint;
+pub use constraints::CheckOperand;
+pub use constraints::Constraints;
+pub use constraints::ForeignKey;
+pub use constraints::ReferentialAction;
+pub use constraints::UniqueConstraint;
 pub use dbtype::Type;
+pub use expression::Expression;
 pub use field::Field;
+pub use field::UuidVersion;
 pub use filters::Filter;
+pub use filters::FilterExpression;
 pub use filters::FilterOption;
 pub use free_space::FreeSpace;
 pub use table_metadata::TableMetadata;
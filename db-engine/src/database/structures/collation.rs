@@ -0,0 +1,99 @@
+use super::Type;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+/// How strings of a field are compared in filters, checks, indexes and keys. Values are
+/// stored as they are, only index keys take the folded form
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Collation {
+    /// By bytes, which is the order of code points
+    #[default]
+    Binary = 0,
+    /// Like binary, but ASCII letters are compared regardless of case
+    CaseInsensitive = 1,
+    /// Letters of any script are compared regardless of case, e.g. 'Straße' is equal
+    /// to 'STRASSE'
+    Unicode = 2,
+}
+
+impl Collation {
+    pub fn from_id(id: u8) -> Result<Self, &'static str> {
+        match id {
+            0 => Ok(Collation::Binary),
+            1 => Ok(Collation::CaseInsensitive),
+            2 => Ok(Collation::Unicode),
+            _ => Err("Unknown collation"),
+        }
+    }
+
+    /// Form of the string that is the same for all strings the collation treats as equal
+    pub fn fold<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        match self {
+            Collation::Binary => Cow::Borrowed(value),
+            Collation::CaseInsensitive => Cow::Owned(value.to_ascii_lowercase()),
+            // Going through upper case folds letters like 'ß' that have no lower case pair
+            Collation::Unicode => Cow::Owned(
+                value
+                    .chars()
+                    .flat_map(char::to_uppercase)
+                    .flat_map(char::to_lowercase)
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Value the string is kept under in indexes and key indexes. Other values are kept
+    /// as they are
+    pub fn key(&self, value: &Type) -> Type {
+        match (self, value) {
+            (Collation::Binary, _) => value.clone(),
+            (_, Type::Varchar(len, var)) => Type::Varchar(*len, self.fold(var).into_owned()),
+            (_, Type::Text(var)) => Type::Text(self.fold(var).into_owned()),
+            _ => value.clone(),
+        }
+    }
+
+    pub fn compare(&self, a: &Type, b: &Type) -> Ordering {
+        match (self, a, b) {
+            (Collation::Binary, _, _) => a.cmp(b),
+            (_, Type::Varchar(_, a) | Type::Text(a), Type::Varchar(_, b) | Type::Text(b)) => {
+                self.fold(a).cmp(&self.fold(b))
+            }
+            _ => a.cmp(b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varchar(value: &str) -> Type {
+        Type::Varchar(16, String::from(value))
+    }
+
+    #[test]
+    fn comparison() {
+        let (street, shout) = (varchar("Straße"), varchar("STRASSE"));
+        let (name, lower) = (varchar("ANNA"), Type::Varchar(4, String::from("anna")));
+        assert_eq!(Collation::Binary.compare(&name, &lower), Ordering::Less);
+        assert_eq!(
+            Collation::CaseInsensitive.compare(&name, &lower),
+            Ordering::Equal
+        );
+        assert_ne!(
+            Collation::CaseInsensitive.compare(&street, &shout),
+            Ordering::Equal
+        );
+        assert_eq!(Collation::Unicode.compare(&street, &shout), Ordering::Equal);
+        assert_eq!(
+            Collation::Unicode.compare(&varchar("Ёлка"), &varchar("ёЛКА")),
+            Ordering::Equal
+        );
+        assert_eq!(
+            Collation::Unicode.key(&street),
+            Collation::Unicode.key(&shout)
+        );
+        assert_eq!(Collation::Unicode.key(&Type::I8(1)), Type::I8(1));
+    }
+}
@@ -0,0 +1,342 @@
+use super::{Collation, Filter, Type, dbtype};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Values of the fields must not repeat among records. Records that have null in any of
+/// the fields never conflict
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueConstraint {
+    pub name: String,
+    pub fields: Vec<u16>,
+}
+
+/// What happens to records that reference a deleted record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReferentialAction {
+    /// The record cannot be deleted while it's referenced
+    #[default]
+    Restrict = 0,
+    /// Referencing records are deleted too
+    Cascade = 1,
+    /// Referencing fields are set to null
+    SetNull = 2,
+}
+
+impl ReferentialAction {
+    fn from_id(id: u8) -> Result<Self, &'static str> {
+        match id {
+            0 => Ok(ReferentialAction::Restrict),
+            1 => Ok(ReferentialAction::Cascade),
+            2 => Ok(ReferentialAction::SetNull),
+            _ => Err("Unknown referential action"),
+        }
+    }
+}
+
+/// Values of the fields must match the primary key of a record of another table, unless
+/// any of them is null
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKey {
+    pub name: String,
+    pub fields: Vec<u16>,
+    /// Name of the referenced table in the same database
+    pub table: String,
+    pub on_delete: ReferentialAction,
+}
+
+/// Value a field is compared with in a check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckOperand {
+    /// Constant of the type of the field, null for 'IsNull' and 'IsNotNull'
+    Value(Type),
+    /// Another field of the same record
+    Field(u16),
+}
+
+/// Condition every record must meet, e.g. 'quantity > 0' or 'start < end'. As in SQL, the
+/// condition holds if any of the compared values is null
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckConstraint {
+    pub name: String,
+    pub field: u16,
+    pub filter: Filter,
+    pub operand: CheckOperand,
+}
+
+impl CheckConstraint {
+    /// Whether the record meets the condition, strings are compared by the collation of
+    /// the checked field
+    pub fn holds(&self, record: &[Type], collation: Collation) -> bool {
+        let value = &record[self.field as usize];
+        let operand = match &self.operand {
+            CheckOperand::Value(value) => value,
+            CheckOperand::Field(field) => &record[*field as usize],
+        };
+        match self.filter {
+            Filter::IsNull | Filter::IsNotNull => self.filter.matches(value, operand, collation),
+            // Comparisons with nulls are unknown, which doesn't break the condition
+            _ if *value == Type::Null || *operand == Type::Null => true,
+            _ => self.filter.matches(value, operand, collation),
+        }
+    }
+
+    /// Fields the condition depends on
+    pub fn fields(&self) -> Vec<u16> {
+        match self.operand {
+            CheckOperand::Value(_) => vec![self.field],
+            CheckOperand::Field(field) => vec![self.field, field],
+        }
+    }
+}
+
+/// Constraints of a table besides the primary key
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Constraints {
+    pub unique: Vec<UniqueConstraint>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub checks: Vec<CheckConstraint>,
+}
+
+impl Constraints {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(&(self.unique.len() as u16).to_le_bytes());
+        for constraint in &self.unique {
+            serialize_name(&constraint.name, &mut buffer);
+            serialize_fields(&constraint.fields, &mut buffer);
+        }
+        buffer.extend_from_slice(&(self.foreign_keys.len() as u16).to_le_bytes());
+        for constraint in &self.foreign_keys {
+            serialize_name(&constraint.name, &mut buffer);
+            serialize_fields(&constraint.fields, &mut buffer);
+            serialize_name(&constraint.table, &mut buffer);
+            buffer.push(constraint.on_delete as u8);
+        }
+        buffer.extend_from_slice(&(self.checks.len() as u16).to_le_bytes());
+        for constraint in &self.checks {
+            serialize_name(&constraint.name, &mut buffer);
+            buffer.extend_from_slice(&constraint.field.to_le_bytes());
+            buffer.push(constraint.filter as u8);
+            match &constraint.operand {
+                // Null filters don't have a value to compare with
+                CheckOperand::Value(Type::Null) => buffer.push(2),
+                // Type of the value followed by the value in the form of a record
+                CheckOperand::Value(value) => {
+                    buffer.push(0);
+                    buffer.extend_from_slice(&value.serialize_definition());
+                    let data = dbtype::serialize_values(
+                        std::slice::from_ref(value),
+                        std::slice::from_ref(value),
+                    );
+                    buffer.extend_from_slice(&(data.len() as u16).to_le_bytes());
+                    buffer.extend_from_slice(&data);
+                }
+                CheckOperand::Field(field) => {
+                    buffer.push(1);
+                    buffer.extend_from_slice(&field.to_le_bytes());
+                }
+            }
+        }
+        buffer
+    }
+
+    pub fn deserialize<R: Read>(rdr: &mut R) -> Result<Self, &'static str> {
+        let unique_count = rdr
+            .read_u16::<LittleEndian>()
+            .map_err(|_| "Missing number of unique constraints")?;
+        let mut unique = Vec::with_capacity(unique_count as usize);
+        for _ in 0..unique_count {
+            unique.push(UniqueConstraint {
+                name: deserialize_name(rdr)?,
+                fields: deserialize_fields(rdr)?,
+            });
+        }
+        let foreign_keys_count = rdr
+            .read_u16::<LittleEndian>()
+            .map_err(|_| "Missing number of foreign keys")?;
+        let mut foreign_keys = Vec::with_capacity(foreign_keys_count as usize);
+        for _ in 0..foreign_keys_count {
+            foreign_keys.push(ForeignKey {
+                name: deserialize_name(rdr)?,
+                fields: deserialize_fields(rdr)?,
+                table: deserialize_name(rdr)?,
+                on_delete: ReferentialAction::from_id(
+                    rdr.read_u8().map_err(|_| "Missing referential action")?,
+                )?,
+            });
+        }
+        let checks_count = rdr
+            .read_u16::<LittleEndian>()
+            .map_err(|_| "Missing number of checks")?;
+        let mut checks = Vec::with_capacity(checks_count as usize);
+        for _ in 0..checks_count {
+            checks.push(CheckConstraint {
+                name: deserialize_name(rdr)?,
+                field: rdr
+                    .read_u16::<LittleEndian>()
+                    .map_err(|_| "Missing checked field")?,
+                filter: Filter::from_id(rdr.read_u8().map_err(|_| "Missing check filter")?)?,
+                operand: deserialize_operand(rdr)?,
+            });
+        }
+        Ok(Constraints {
+            unique,
+            foreign_keys,
+            checks,
+        })
+    }
+
+    /// Names of all constraints of the table
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let unique = self.unique.iter().map(|constraint| &constraint.name);
+        let foreign_keys = self.foreign_keys.iter().map(|constraint| &constraint.name);
+        let checks = self.checks.iter().map(|constraint| &constraint.name);
+        unique.chain(foreign_keys).chain(checks).map(String::as_str)
+    }
+}
+
+fn serialize_name(name: &str, buffer: &mut Vec<u8>) {
+    // Names of constraints and referenced tables are checked by TableMetadata::new
+    buffer.push(name.len() as u8);
+    buffer.extend_from_slice(name.as_bytes());
+}
+
+fn deserialize_name<R: Read>(rdr: &mut R) -> Result<String, &'static str> {
+    let len = rdr
+        .read_u8()
+        .map_err(|_| "Missing constraint name length")?;
+    let mut name = vec![0u8; len as usize];
+    rdr.read_exact(&mut name)
+        .map_err(|_| "Incomplete constraint name")?;
+    String::from_utf8(name).map_err(|_| "Invalid UTF-8 in constraint name")
+}
+
+fn serialize_fields(fields: &[u16], buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(fields.len() as u16).to_le_bytes());
+    for field in fields {
+        buffer.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+fn deserialize_fields<R: Read>(rdr: &mut R) -> Result<Vec<u16>, &'static str> {
+    let count = rdr
+        .read_u16::<LittleEndian>()
+        .map_err(|_| "Missing number of constraint fields")?;
+    (0..count)
+        .map(|_| {
+            rdr.read_u16::<LittleEndian>()
+                .map_err(|_| "Incomplete constraint fields")
+        })
+        .collect()
+}
+
+fn deserialize_operand<R: Read>(rdr: &mut R) -> Result<CheckOperand, &'static str> {
+    match rdr.read_u8().map_err(|_| "Missing check operand")? {
+        0 => {
+            let type_ = Type::deserialize_definition(rdr)?;
+            let len = rdr
+                .read_u16::<LittleEndian>()
+                .map_err(|_| "Missing length of check value")?;
+            let mut data = vec![0u8; len as usize];
+            rdr.read_exact(&mut data)
+                .map_err(|_| "Incomplete check value")?;
+            let mut values = dbtype::deserialize_value(&data, &[type_])?;
+            Ok(CheckOperand::Value(values.remove(0)))
+        }
+        1 => Ok(CheckOperand::Field(
+            rdr.read_u16::<LittleEndian>()
+                .map_err(|_| "Missing compared field")?,
+        )),
+        2 => Ok(CheckOperand::Value(Type::Null)),
+        _ => Err("Unknown check operand"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialization_deserialization() {
+        let constraints = Constraints {
+            unique: vec![
+                UniqueConstraint {
+                    name: String::from("unique_email"),
+                    fields: vec![2],
+                },
+                UniqueConstraint {
+                    name: String::from("unique_tenant_login"),
+                    fields: vec![0, 3],
+                },
+            ],
+            foreign_keys: vec![ForeignKey {
+                name: String::from("fk_customer"),
+                fields: vec![1],
+                table: String::from("customers"),
+                on_delete: ReferentialAction::SetNull,
+            }],
+            checks: vec![
+                CheckConstraint {
+                    name: String::from("check_quantity"),
+                    field: 3,
+                    filter: Filter::Greater,
+                    operand: CheckOperand::Value(Type::I32(0)),
+                },
+                CheckConstraint {
+                    name: String::from("check_start"),
+                    field: 4,
+                    filter: Filter::Less,
+                    operand: CheckOperand::Field(5),
+                },
+                CheckConstraint {
+                    name: String::from("check_code"),
+                    field: 6,
+                    filter: Filter::IsNotNull,
+                    operand: CheckOperand::Value(Type::Null),
+                },
+            ],
+        };
+        let serialized = constraints.serialize();
+        assert_eq!(
+            Constraints::deserialize(&mut serialized.as_slice()),
+            Ok(constraints)
+        );
+    }
+
+    #[test]
+    fn checks() {
+        let check = |field, filter, operand| CheckConstraint {
+            name: String::from("check"),
+            field,
+            filter,
+            operand,
+        };
+        let quantity = check(0, Filter::Greater, CheckOperand::Value(Type::I32(0)));
+        let period = check(1, Filter::Less, CheckOperand::Field(2));
+        let code = check(3, Filter::StartsWith, CheckOperand::Value(varchar("A-")));
+        let record = |quantity, start, end, code| vec![quantity, start, end, code];
+        let holds = |record: &[Type]| {
+            [&quantity, &period, &code]
+                .iter()
+                .map(|check| check.holds(record, Collation::Binary))
+                .collect::<Vec<_>>()
+        };
+        let valid = record(Type::I32(1), Type::Date(1), Type::Date(2), varchar("A-1"));
+        assert_eq!(holds(&valid), [true, true, true]);
+        let invalid = record(Type::I32(0), Type::Date(2), Type::Date(2), varchar("B-1"));
+        assert_eq!(holds(&invalid), [false, false, false]);
+        // Unknown results don't break the condition
+        let nulls = record(Type::Null, Type::Date(2), Type::Null, Type::Null);
+        assert_eq!(holds(&nulls), [true, true, true]);
+        let not_null = check(0, Filter::IsNotNull, CheckOperand::Value(Type::Null));
+        assert!(!not_null.holds(&nulls, Collation::Binary));
+
+        let lower = record(Type::I32(1), Type::Date(1), Type::Date(2), varchar("a-1"));
+        assert!(!code.holds(&lower, Collation::Binary));
+        assert!(code.holds(&lower, Collation::CaseInsensitive));
+    }
+
+    fn varchar(value: &str) -> Type {
+        Type::Varchar(8, String::from(value))
+    }
+}
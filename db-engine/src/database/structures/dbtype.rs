@@ -1,10 +1,15 @@
 use bincode::{Decode, Encode};
+use byteorder::ReadBytesExt;
 use std::cmp::Ordering;
+use std::io::Read;
+use std::sync::Arc;
 
 #[repr(u8)]
 #[derive(Debug, Clone, Encode, Decode)]
 pub enum Type {
     //String(String),
+    /// Absence of a value. It is never a type of a field
+    Null = 0,
     I8(i8) = 1,
     I16(i16) = 2,
     I32(i32) = 3,
@@ -17,21 +22,69 @@ pub enum Type {
     U128(u128) = 10,
     Varchar(u8, String) = 11,
     Boolean(bool) = 12,
+    F32(f32) = 13,
+    F64(f64) = 14,
+    /// Days since 1970-01-01
+    Date(i32) = 15,
+    /// Microseconds since midnight
+    Time(u64) = 16,
+    /// Microseconds since 1970-01-01 00:00:00 UTC
+    Timestamp(i64) = 17,
+    /// Microseconds since 1970-01-01 00:00:00 UTC and the offset of the time zone in minutes.
+    /// Values are compared only by the moment they point to
+    TimestampTz(i64, i16) = 18,
+    /// Precision, scale and the unscaled value, e.g. 123.45 is stored as 12345 with scale 2
+    Decimal(u8, u8, i128) = 19,
+    Text(String) = 20,
+    Blob(Vec<u8>) = 21,
+    /// Length and id of a text or blob value that doesn't fit in the record and is stored
+    /// in an overflow page. Never leaves the database, values are loaded before that
+    Overflow(u32, u64) = 22,
+    /// Exactly the declared number of bytes
+    Binary(u8, Vec<u8>) = 23,
+    /// Up to the declared number of bytes
+    Bytes(u8, Vec<u8>) = 24,
+    Uuid(u128) = 25,
+    /// Labels of the field and the ordinal of the value among them. Only the ordinal is
+    /// stored in the record, values are compared by their labels
+    Enum(Arc<[String]>, u8) = 26,
+    /// Json document in the binary form, stored like a blob
+    Json(Vec<u8>) = 27,
 }
 
+/// Number of microseconds in a day, upper bound (exclusive) of 'Time'
+pub const MICROS_PER_DAY: u64 = 86_400_000_000;
+/// Maximum absolute offset of a time zone in minutes
+pub const MAX_TZ_OFFSET_MINUTES: i16 = 18 * 60;
+/// Maximum number of digits a decimal can hold, so its unscaled value fits in i128
+pub const MAX_DECIMAL_PRECISION: u8 = 38;
+/// Longest text or blob (in bytes) that is stored right in the record
+pub const INLINE_CAPACITY: usize = 32;
+/// Texts and blobs take 4 bytes of length followed by either the data or the id of the
+/// overflow page
+const OVERFLOW_SLOT_SIZE: u16 = 4 + INLINE_CAPACITY as u16;
+
 impl Type {
     pub fn type_id(&self) -> u8 {
         unsafe { *<*const _>::from(self).cast::<u8>() }
     }
 
-    pub fn size(&self) -> u8 {
+    /// Number of bytes the value takes in a record
+    pub fn size(&self) -> u16 {
         match self {
-            Type::I8(_) | Type::U8(_) | Type::Boolean(_) => 1,
+            Type::Null => 0,
+            Type::I8(_) | Type::U8(_) | Type::Boolean(_) | Type::Enum(_, _) => 1,
             Type::I16(_) | Type::U16(_) => 2,
-            Type::I32(_) | Type::U32(_) => 3,
-            Type::I64(_) | Type::U64(_) => 4,
-            Type::I128(_) | Type::U128(_) => 5,
-            Type::Varchar(len, _) => *len,
+            Type::I32(_) | Type::U32(_) | Type::F32(_) | Type::Date(_) => 4,
+            Type::I64(_) | Type::U64(_) | Type::F64(_) | Type::Time(_) | Type::Timestamp(_) => 8,
+            Type::TimestampTz(_, _) => 10,
+            Type::Text(_) | Type::Blob(_) | Type::Json(_) | Type::Overflow(_, _) => {
+                OVERFLOW_SLOT_SIZE
+            }
+            Type::I128(_) | Type::U128(_) | Type::Decimal(_, _, _) | Type::Uuid(_) => 16,
+            // One byte for the actual length of the string followed by the padded data
+            Type::Varchar(len, _) | Type::Bytes(len, _) => *len as u16 + 1,
+            Type::Binary(len, _) => *len as u16,
         }
     }
 
@@ -49,10 +102,87 @@ impl Type {
             10 => Type::U128(0),
             11 => Type::Varchar(255, String::new()), // default varchar length
             12 => Type::Boolean(false),
+            13 => Type::F32(0.0),
+            14 => Type::F64(0.0),
+            15 => Type::Date(0),
+            16 => Type::Time(0),
+            17 => Type::Timestamp(0),
+            18 => Type::TimestampTz(0, 0),
+            19 => Type::Decimal(MAX_DECIMAL_PRECISION, 0, 0),
+            20 => Type::Text(String::new()),
+            21 => Type::Blob(Vec::new()),
+            23 => Type::Binary(u8::MAX, Vec::new()),
+            24 => Type::Bytes(u8::MAX, Vec::new()),
+            25 => Type::Uuid(0),
+            26 => Type::Enum(Arc::from([]), 0),
+            27 => Type::Json(Vec::new()),
             _ => panic!("Unknown type id: {}", id),
         }
     }
 
+    /// Serializes type id together with the parameters of the type (e.g. length of varchar)
+    pub fn serialize_definition(&self) -> Vec<u8> {
+        match self {
+            Type::Varchar(len, _) | Type::Binary(len, _) | Type::Bytes(len, _) => {
+                vec![self.type_id(), *len]
+            }
+            Type::Decimal(precision, scale, _) => vec![self.type_id(), *precision, *scale],
+            // Number of labels followed by the labels, each prefixed with its length
+            Type::Enum(labels, _) => {
+                let mut result = vec![self.type_id(), labels.len() as u8];
+                for label in labels.iter() {
+                    result.push(label.len() as u8);
+                    result.extend_from_slice(label.as_bytes());
+                }
+                result
+            }
+            _ => vec![self.type_id()],
+        }
+    }
+
+    pub fn deserialize_definition<R: Read>(rdr: &mut R) -> Result<Self, &'static str> {
+        let type_id = rdr.read_u8().map_err(|_| "Missing type id")?;
+        match type_id {
+            11 => {
+                let len = rdr.read_u8().map_err(|_| "Missing varchar length")?;
+                Ok(Type::Varchar(len, String::new()))
+            }
+            23 | 24 => {
+                let len = rdr.read_u8().map_err(|_| "Missing bytes length")?;
+                match Type::from_type_id(type_id) {
+                    Type::Binary(_, data) => Ok(Type::Binary(len, data)),
+                    _ => Ok(Type::Bytes(len, Vec::new())),
+                }
+            }
+            19 => {
+                let precision = rdr.read_u8().map_err(|_| "Missing decimal precision")?;
+                let scale = rdr.read_u8().map_err(|_| "Missing decimal scale")?;
+                if precision == 0 || precision > MAX_DECIMAL_PRECISION || scale > precision {
+                    return Err("Invalid decimal precision or scale");
+                }
+                Ok(Type::Decimal(precision, scale, 0))
+            }
+            26 => {
+                let count = rdr.read_u8().map_err(|_| "Missing number of enum labels")?;
+                if count == 0 {
+                    return Err("Enum has no labels");
+                }
+                let mut labels = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let len = rdr.read_u8().map_err(|_| "Missing enum label length")?;
+                    let mut label = vec![0u8; len as usize];
+                    rdr.read_exact(&mut label)
+                        .map_err(|_| "Incomplete enum label")?;
+                    labels
+                        .push(String::from_utf8(label).map_err(|_| "Invalid UTF-8 in enum label")?);
+                }
+                Ok(Type::Enum(labels.into(), 0))
+            }
+            1..=18 | 20 | 21 | 25 | 27 => Ok(Type::from_type_id(type_id)),
+            _ => Err("Unknown type id"),
+        }
+    }
+
     fn serialize(&self) -> Vec<u8> {
         macro_rules! num_to_bytes {
             ($var:expr) => {
@@ -60,6 +190,7 @@ impl Type {
             };
         }
         match self {
+            Type::Null => Vec::new(),
             Type::I8(var) => num_to_bytes!(var),
             Type::U8(var) => num_to_bytes!(var),
             Type::Boolean(var) => vec![*var as u8],
@@ -71,7 +202,35 @@ impl Type {
             Type::U64(var) => num_to_bytes!(var),
             Type::I128(var) => num_to_bytes!(var),
             Type::U128(var) => num_to_bytes!(var),
-            Type::Varchar(_, var) => var.as_bytes().to_vec(),
+            Type::F32(var) => num_to_bytes!(var),
+            Type::F64(var) => num_to_bytes!(var),
+            Type::Date(var) => num_to_bytes!(var),
+            Type::Time(var) => num_to_bytes!(var),
+            Type::Timestamp(var) => num_to_bytes!(var),
+            Type::TimestampTz(var, offset) => {
+                let mut result = num_to_bytes!(var);
+                result.extend_from_slice(&offset.to_le_bytes());
+                result
+            }
+            Type::Decimal(_, _, var) => num_to_bytes!(var),
+            Type::Text(var) => serialize_inline(var.as_bytes()),
+            Type::Blob(var) | Type::Json(var) => serialize_inline(var),
+            Type::Overflow(len, id) => {
+                let mut result = vec![0; OVERFLOW_SLOT_SIZE as usize];
+                result[..4].copy_from_slice(&len.to_le_bytes());
+                result[4..12].copy_from_slice(&id.to_le_bytes());
+                result
+            }
+            Type::Binary(len, var) => {
+                let mut result = var.clone();
+                result.resize(*len as usize, 0);
+                result
+            }
+            Type::Bytes(len, var) => serialize_bounded(*len, var),
+            // Big endian keeps the canonical order of bytes
+            Type::Uuid(var) => var.to_be_bytes().to_vec(),
+            Type::Varchar(len, var) => serialize_bounded(*len, var.as_bytes()),
+            Type::Enum(_, ordinal) => vec![*ordinal],
         }
     }
 
@@ -80,6 +239,7 @@ impl Type {
             return Err("Length of the buffer is less than length of expected type.");
         }
         match type_ {
+            Type::Null => Ok(Type::Null),
             Type::Boolean(_) => Ok(Type::Boolean(data[0] != 0)),
             Type::I8(_) => Ok(Type::I8(i8::from_le_bytes([data[0]]))),
             Type::U8(_) => Ok(Type::U8(u8::from_le_bytes([data[0]]))),
@@ -95,17 +255,150 @@ impl Type {
             Type::U128(_) => Ok(Type::U128(u128::from_le_bytes(
                 data[..16].try_into().unwrap(),
             ))),
-            Type::Varchar(len, _) => Ok(Type::Varchar(
-                *len,
-                std::str::from_utf8(&data[..(*len as usize)])
-                    .unwrap()
-                    .to_string(),
+            Type::F32(_) => Ok(Type::F32(f32::from_le_bytes(data[..4].try_into().unwrap()))),
+            Type::F64(_) => Ok(Type::F64(f64::from_le_bytes(data[..8].try_into().unwrap()))),
+            Type::Date(_) => Ok(Type::Date(i32::from_le_bytes(
+                data[..4].try_into().unwrap(),
+            ))),
+            Type::Time(_) => Ok(Type::Time(u64::from_le_bytes(
+                data[..8].try_into().unwrap(),
+            ))),
+            Type::Timestamp(_) => Ok(Type::Timestamp(i64::from_le_bytes(
+                data[..8].try_into().unwrap(),
+            ))),
+            Type::Text(_) | Type::Blob(_) | Type::Json(_) | Type::Overflow(_, _) => {
+                let len = u32::from_le_bytes(data[..4].try_into().unwrap());
+                if len as usize > INLINE_CAPACITY {
+                    let id = u64::from_le_bytes(data[4..12].try_into().unwrap());
+                    return Ok(Type::Overflow(len, id));
+                }
+                let var = data[4..(4 + len as usize)].to_vec();
+                match type_ {
+                    Type::Text(_) => match String::from_utf8(var) {
+                        Ok(var) => Ok(Type::Text(var)),
+                        Err(_) => Err("Text contains invalid UTF-8."),
+                    },
+                    Type::Json(_) => Ok(Type::Json(var)),
+                    _ => Ok(Type::Blob(var)),
+                }
+            }
+            Type::Decimal(precision, scale, _) => Ok(Type::Decimal(
+                *precision,
+                *scale,
+                i128::from_le_bytes(data[..16].try_into().unwrap()),
+            )),
+            Type::TimestampTz(_, _) => Ok(Type::TimestampTz(
+                i64::from_le_bytes(data[..8].try_into().unwrap()),
+                i16::from_le_bytes(data[8..10].try_into().unwrap()),
             )),
+            Type::Uuid(_) => Ok(Type::Uuid(u128::from_be_bytes(
+                data[..16].try_into().unwrap(),
+            ))),
+            Type::Binary(len, _) => Ok(Type::Binary(*len, data[..(*len as usize)].to_vec())),
+            Type::Enum(labels, _) => match (data[0] as usize) < labels.len() {
+                true => Ok(Type::Enum(labels.clone(), data[0])),
+                false => Err("Ordinal of the enum is out of the list of labels."),
+            },
+            Type::Bytes(len, _) => {
+                let data_len = data[0] as usize;
+                if data_len > *len as usize {
+                    return Err("Length of the bytes is bigger than the declared length.");
+                }
+                Ok(Type::Bytes(*len, data[1..(data_len + 1)].to_vec()))
+            }
+            Type::Varchar(len, _) => {
+                let data_len = data[0] as usize;
+                if data_len > *len as usize {
+                    return Err("Length of the string is bigger than length of the varchar.");
+                }
+                match std::str::from_utf8(&data[1..(data_len + 1)]) {
+                    Ok(var) => Ok(Type::Varchar(*len, var.to_string())),
+                    Err(_) => Err("Varchar contains invalid UTF-8."),
+                }
+            }
         }
     }
 
+    /// Whether the value is a text, a blob or a json too long to be stored in the record
+    pub fn needs_overflow(&self) -> bool {
+        match self {
+            Type::Text(var) => var.len() > INLINE_CAPACITY,
+            Type::Blob(var) | Type::Json(var) => var.len() > INLINE_CAPACITY,
+            _ => false,
+        }
+    }
+
+    /// Value of an integer as i128, None for other types and u128 values that don't fit
+    pub fn integer_value(&self) -> Option<i128> {
+        match self {
+            Type::I8(var) => Some(*var as i128),
+            Type::I16(var) => Some(*var as i128),
+            Type::I32(var) => Some(*var as i128),
+            Type::I64(var) => Some(*var as i128),
+            Type::I128(var) => Some(*var),
+            Type::U8(var) => Some(*var as i128),
+            Type::U16(var) => Some(*var as i128),
+            Type::U32(var) => Some(*var as i128),
+            Type::U64(var) => Some(*var as i128),
+            Type::U128(var) => i128::try_from(*var).ok(),
+            _ => None,
+        }
+    }
+
+    /// Integer of the same type as self with the given value, None if self is not an
+    /// integer or the value is out of its range
+    pub fn with_integer_value(&self, value: i128) -> Option<Type> {
+        match self {
+            Type::I8(_) => value.try_into().ok().map(Type::I8),
+            Type::I16(_) => value.try_into().ok().map(Type::I16),
+            Type::I32(_) => value.try_into().ok().map(Type::I32),
+            Type::I64(_) => value.try_into().ok().map(Type::I64),
+            Type::I128(_) => Some(Type::I128(value)),
+            Type::U8(_) => value.try_into().ok().map(Type::U8),
+            Type::U16(_) => value.try_into().ok().map(Type::U16),
+            Type::U32(_) => value.try_into().ok().map(Type::U32),
+            Type::U64(_) => value.try_into().ok().map(Type::U64),
+            Type::U128(_) => value.try_into().ok().map(Type::U128),
+            _ => None,
+        }
+    }
+
+    /// Whether the value is an integer, a decimal or a float
+    pub fn is_number(&self) -> bool {
+        matches!(
+            self,
+            Type::I8(_)
+                | Type::I16(_)
+                | Type::I32(_)
+                | Type::I64(_)
+                | Type::I128(_)
+                | Type::U8(_)
+                | Type::U16(_)
+                | Type::U32(_)
+                | Type::U64(_)
+                | Type::U128(_)
+                | Type::Decimal(_, _, _)
+                | Type::F32(_)
+                | Type::F64(_)
+        )
+    }
+
+    /// Floats are ordered totally: -0.0 is equal to 0.0 and every NaN is equal to
+    /// any other NaN and greater than positive infinity
     fn data_cmp(&self, other: &Self) -> Ordering {
+        macro_rules! canonical_float {
+            ($var:expr, $type:ty) => {
+                if $var.is_nan() {
+                    <$type>::NAN
+                } else if *$var == 0.0 {
+                    0.0
+                } else {
+                    *$var
+                }
+            };
+        }
         match (self, other) {
+            (Type::Null, Type::Null) => Ordering::Equal,
             (Type::I8(a), Type::I8(b)) => a.cmp(b),
             (Type::I16(a), Type::I16(b)) => a.cmp(b),
             (Type::I32(a), Type::I32(b)) => a.cmp(b),
@@ -116,14 +409,32 @@ impl Type {
             (Type::U32(a), Type::U32(b)) => a.cmp(b),
             (Type::U64(a), Type::U64(b)) => a.cmp(b),
             (Type::U128(a), Type::U128(b)) => a.cmp(b),
-            (Type::Varchar(len_a, s_a), Type::Varchar(len_b, s_b)) => {
-                // Compare length first, then string
-                match len_a.cmp(len_b) {
-                    Ordering::Equal => s_a.cmp(s_b),
-                    ordering => ordering,
-                }
-            }
+            // Declared length is a property of the field, not of the value
+            (Type::Varchar(_, a), Type::Varchar(_, b)) => a.cmp(b),
             (Type::Boolean(a), Type::Boolean(b)) => a.cmp(b),
+            (Type::F32(a), Type::F32(b)) => {
+                canonical_float!(a, f32).total_cmp(&canonical_float!(b, f32))
+            }
+            (Type::Date(a), Type::Date(b)) => a.cmp(b),
+            (Type::Time(a), Type::Time(b)) => a.cmp(b),
+            (Type::Timestamp(a), Type::Timestamp(b)) => a.cmp(b),
+            (Type::TimestampTz(a, _), Type::TimestampTz(b, _)) => a.cmp(b),
+            (Type::Text(a), Type::Text(b)) => a.cmp(b),
+            (Type::Blob(a), Type::Blob(b)) => a.cmp(b),
+            (Type::Json(a), Type::Json(b)) => a.cmp(b),
+            (Type::Binary(_, a), Type::Binary(_, b)) => a.cmp(b),
+            (Type::Bytes(_, a), Type::Bytes(_, b)) => a.cmp(b),
+            (Type::Uuid(a), Type::Uuid(b)) => a.cmp(b),
+            (Type::Enum(labels_a, a), Type::Enum(labels_b, b)) => {
+                labels_a.get(*a as usize).cmp(&labels_b.get(*b as usize))
+            }
+            (Type::Overflow(_, a), Type::Overflow(_, b)) => a.cmp(b),
+            (Type::Decimal(_, scale_a, a), Type::Decimal(_, scale_b, b)) => {
+                decimal_cmp(*a, *scale_a, *b, *scale_b)
+            }
+            (Type::F64(a), Type::F64(b)) => {
+                canonical_float!(a, f64).total_cmp(&canonical_float!(b, f64))
+            }
             _ => Ordering::Equal, // Should never happen since we check discriminant first
         }
     }
@@ -152,11 +463,152 @@ impl Ord for Type {
     }
 }
 
-pub fn serialize_values(data: &[Type]) -> Vec<u8> {
-    let size = data.iter().map(|t| t.size() as usize).sum();
-    let mut result = Vec::with_capacity(size);
-    data.iter()
-        .for_each(|data| result.extend_from_slice(&data.serialize()));
+/// Serializes data as its length followed by the data padded to the declared length
+fn serialize_bounded(len: u8, data: &[u8]) -> Vec<u8> {
+    let len = len as usize;
+    let data = &data[..data.len().min(len)];
+    let mut result = vec![0; len + 1];
+    result[0] = data.len() as u8;
+    result[1..(data.len() + 1)].copy_from_slice(data);
+    result
+}
+
+fn serialize_inline(data: &[u8]) -> Vec<u8> {
+    if data.len() > INLINE_CAPACITY {
+        panic!(
+            "Value of {} bytes must be moved to an overflow page",
+            data.len()
+        );
+    }
+    let mut result = vec![0; OVERFLOW_SLOT_SIZE as usize];
+    result[..4].copy_from_slice(&(data.len() as u32).to_le_bytes());
+    result[4..(4 + data.len())].copy_from_slice(data);
+    result
+}
+
+/// Exactly compares decimals with different scales. Integer parts are compared first,
+/// then fractional parts are brought to the same scale, which can't overflow
+fn decimal_cmp(a: i128, scale_a: u8, b: i128, scale_b: u8) -> Ordering {
+    let divisor_a = 10i128.pow(scale_a as u32);
+    let divisor_b = 10i128.pow(scale_b as u32);
+    match (a / divisor_a).cmp(&(b / divisor_b)) {
+        Ordering::Equal => {
+            let scale = scale_a.max(scale_b);
+            let fraction_a = (a % divisor_a) * 10i128.pow((scale - scale_a) as u32);
+            let fraction_b = (b % divisor_b) * 10i128.pow((scale - scale_b) as u32);
+            fraction_a.cmp(&fraction_b)
+        }
+        ordering => ordering,
+    }
+}
+
+/// Brings unscaled value of a decimal to another scale. Returns None if the value
+/// doesn't fit or if digits would be lost
+pub fn rescale_decimal(value: i128, from: u8, to: u8) -> Option<i128> {
+    if from <= to {
+        value.checked_mul(10i128.checked_pow((to - from) as u32)?)
+    } else {
+        let divisor = 10i128.pow((from - to) as u32);
+        match value % divisor {
+            0 => Some(value / divisor),
+            _ => None,
+        }
+    }
+}
+
+/// Converts a number to the numeric type of `type_` (with its precision and scale for
+/// decimals) if nothing is lost: integers must be in range, decimals must keep all their
+/// digits and floats must keep their shortest decimal form, e.g. 0.1 becomes f32 0.1,
+/// while 16777217 doesn't fit f32. Returns None otherwise
+pub fn coerce_number(value: &Type, type_: &Type) -> Option<Type> {
+    if value.type_id() == type_.type_id() && !matches!(type_, Type::Decimal(_, _, _)) {
+        return Some(value.clone());
+    }
+    let text = match value {
+        Type::F32(var) => var.to_string(),
+        Type::F64(var) => var.to_string(),
+        Type::Decimal(_, scale, var) => decimal_to_string(*var, *scale),
+        Type::U128(var) => var.to_string(),
+        value => value.integer_value()?.to_string(),
+    };
+    match type_ {
+        Type::F32(_) => {
+            let var: f32 = text.parse().ok()?;
+            (var.to_string() == text).then_some(Type::F32(var))
+        }
+        Type::F64(_) => {
+            let var: f64 = text.parse().ok()?;
+            (var.to_string() == text).then_some(Type::F64(var))
+        }
+        Type::Decimal(precision, scale, _) => {
+            let (var, var_scale) = parse_decimal(&text)?;
+            let var = rescale_decimal(var, var_scale, *scale)?;
+            (var.unsigned_abs() < 10u128.pow(*precision as u32))
+                .then_some(Type::Decimal(*precision, *scale, var))
+        }
+        Type::U128(_) => text.parse().ok().map(Type::U128),
+        type_ => {
+            let (var, var_scale) = parse_decimal(&text)?;
+            type_.with_integer_value(rescale_decimal(var, var_scale, 0)?)
+        }
+    }
+}
+
+/// Decimal without trailing zeros in the fractional part, which is also how floats are
+/// printed
+fn decimal_to_string(value: i128, scale: u8) -> String {
+    let divisor = 10u128.pow(scale as u32);
+    let (integer, fraction) = (
+        value.unsigned_abs() / divisor,
+        value.unsigned_abs() % divisor,
+    );
+    let sign = if value < 0 { "-" } else { "" };
+    match fraction {
+        0 => format!("{}{}", sign, integer),
+        _ => {
+            let fraction = format!("{:0width$}", fraction, width = scale as usize);
+            format!("{}{}.{}", sign, integer, fraction.trim_end_matches('0'))
+        }
+    }
+}
+
+/// Unscaled value and scale of a number written in decimal notation
+fn parse_decimal(text: &str) -> Option<(i128, u8)> {
+    let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+    let scale = u8::try_from(fraction.len()).ok()?;
+    if scale > MAX_DECIMAL_PRECISION {
+        return None;
+    }
+    let value = format!("{}{}", integer, fraction).parse().ok()?;
+    Some((value, scale))
+}
+
+/// Size of the null bitmap that precedes values of a record
+fn null_bitmap_size(record_structure: &[Type]) -> usize {
+    record_structure.len().div_ceil(8)
+}
+
+/// Size of a record with the given structure including its null bitmap
+pub fn record_size(record_structure: &[Type]) -> u16 {
+    null_bitmap_size(record_structure) as u16
+        + record_structure.iter().map(|t| t.size()).sum::<u16>()
+}
+
+/// Serializes a record as a null bitmap followed by values. Null values take as much
+/// space as values of their field so the record keeps the same layout
+pub fn serialize_values(data: &[Type], record_structure: &[Type]) -> Vec<u8> {
+    let bitmap_size = null_bitmap_size(record_structure);
+    let mut result = vec![0; bitmap_size];
+    result.reserve(record_size(record_structure) as usize - bitmap_size);
+    for (i, (value, type_)) in data.iter().zip(record_structure).enumerate() {
+        match value {
+            Type::Null => {
+                result[i / 8] |= 1 << (i % 8);
+                result.resize(result.len() + type_.size() as usize, 0);
+            }
+            value => result.extend_from_slice(&value.serialize()),
+        }
+    }
     result
 }
 
@@ -164,17 +616,20 @@ pub fn deserialize_value(
     data: &[u8],
     record_structure: &[Type],
 ) -> Result<Vec<Type>, &'static str> {
-    let record_len = record_structure.iter().map(|t| t.size() as usize).sum();
-    if data.len() < record_len {
+    if data.len() < record_size(record_structure) as usize {
         return Err("Buffer is too short.");
     }
     let mut result = Vec::with_capacity(record_structure.len());
-    let mut cursor = 0;
-    for t in record_structure {
-        result.push(Type::deserialize(
-            &data[cursor..(cursor + t.size() as usize)],
-            t,
-        )?);
+    let mut cursor = null_bitmap_size(record_structure);
+    for (i, t) in record_structure.iter().enumerate() {
+        if data[i / 8] & (1 << (i % 8)) != 0 {
+            result.push(Type::Null);
+        } else {
+            result.push(Type::deserialize(
+                &data[cursor..(cursor + t.size() as usize)],
+                t,
+            )?);
+        }
         cursor += t.size() as usize;
     }
     Ok(result)
@@ -200,16 +655,228 @@ mod tests {
             Type::I128(10_000_000),
             Type::U128(20_000_000),
             Type::Varchar(5, String::from("Hello")),
+            Type::Varchar(10, String::from("Hello")),
             Type::Varchar(0, String::new()),
+            Type::F32(1.5),
+            Type::F64(-2.25),
+            Type::Date(-365),
+            Type::Time(MICROS_PER_DAY - 1),
+            Type::Timestamp(1_700_000_000_000_000),
+            Type::TimestampTz(1_700_000_000_000_000, -300),
+            Type::Decimal(10, 2, -12345),
+            Type::Text(String::from("Hello")),
+            Type::Text("a".repeat(INLINE_CAPACITY)),
+            Type::Blob(vec![0, 255, 1]),
+            Type::Binary(4, vec![0xde, 0xad, 0xbe, 0xef]),
+            Type::Bytes(8, vec![0xff, 0x00]),
+            Type::Uuid(0x0192_3d4e_5f60_7a8b_9cad_bec0_d1e2_f3a4),
+            Type::Enum(Arc::from([String::from("new"), String::from("paid")]), 1),
         ];
 
         for value in test_cases {
             let serialized = value.serialize();
             let deserialized = Type::deserialize(&serialized, &value).unwrap();
+            assert_eq!(serialized.len(), value.size() as usize);
             assert_eq!(deserialized, value);
         }
     }
 
+    #[test]
+    fn record_layout_is_fixed() {
+        let structure = vec![Type::Varchar(8, String::new()), Type::I32(0)];
+        let short = vec![Type::Varchar(8, String::from("a")), Type::I32(1)];
+        let long = vec![Type::Varchar(8, String::from("abcdefgh")), Type::I32(2)];
+        let short_serialized = serialize_values(&short, &structure);
+        let long_serialized = serialize_values(&long, &structure);
+        assert_eq!(short_serialized.len(), long_serialized.len());
+        assert_eq!(
+            deserialize_value(&short_serialized, &structure).unwrap(),
+            short
+        );
+        assert_eq!(
+            deserialize_value(&long_serialized, &structure).unwrap(),
+            long
+        );
+    }
+
+    #[test]
+    fn overflow_references() {
+        let text = Type::Text("a".repeat(INLINE_CAPACITY + 1));
+        assert!(text.needs_overflow());
+        let reference = Type::Overflow(INLINE_CAPACITY as u32 + 1, 7);
+        let serialized = reference.serialize();
+        assert_eq!(serialized.len(), text.size() as usize);
+        assert_eq!(Type::deserialize(&serialized, &text).unwrap(), reference);
+        assert_eq!(
+            Type::deserialize(&serialized, &Type::Blob(Vec::new())).unwrap(),
+            reference
+        );
+    }
+
+    #[test]
+    fn null_values() {
+        let structure: Vec<Type> = (0..10).map(|_| Type::I16(0)).collect();
+        let mut record = structure.clone();
+        record[0] = Type::Null;
+        record[9] = Type::Null;
+        let serialized = serialize_values(&record, &structure);
+        assert_eq!(serialized.len(), record_size(&structure) as usize);
+        assert_eq!(serialized.len(), 22);
+        assert_eq!(deserialize_value(&serialized, &structure).unwrap(), record);
+
+        assert_eq!(Type::Null, Type::Null);
+        assert!(Type::Null < Type::I8(i8::MIN));
+    }
+
+    #[test]
+    fn float_ordering() {
+        assert_eq!(Type::F64(-0.0), Type::F64(0.0));
+        assert_eq!(Type::F32(f32::NAN), Type::F32(-f32::NAN));
+        assert!(Type::F64(f64::NAN) > Type::F64(f64::INFINITY));
+        assert!(Type::F32(f32::NEG_INFINITY) < Type::F32(-1.0));
+        assert!(Type::F32(-1.0) < Type::F32(-0.0));
+
+        let mut index = std::collections::BTreeMap::new();
+        index.insert(Type::F64(0.0), 1);
+        index.insert(Type::F64(-0.0), 2);
+        index.insert(Type::F64(f64::NAN), 3);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.get(&Type::F64(0.0)), Some(&2));
+    }
+
+    #[test]
+    fn timestamp_tz_ordering() {
+        // 12:00 UTC and 15:00 at UTC+3 are the same moment
+        let noon = 12 * 3_600_000_000;
+        assert_eq!(Type::TimestampTz(noon, 0), Type::TimestampTz(noon, 180));
+        assert!(Type::TimestampTz(noon, 180) < Type::TimestampTz(noon + 1, -180));
+    }
+
+    #[test]
+    fn decimal_ordering() {
+        // 1.5 == 1.50
+        assert_eq!(Type::Decimal(5, 1, 15), Type::Decimal(5, 2, 150));
+        // -1.5 < -1.25 < -0.5 < 0.2 < 1.05 < 1.1
+        let ordered = [
+            Type::Decimal(5, 1, -15),
+            Type::Decimal(5, 2, -125),
+            Type::Decimal(5, 1, -5),
+            Type::Decimal(5, 3, 200),
+            Type::Decimal(5, 2, 105),
+            Type::Decimal(5, 1, 11),
+        ];
+        assert!(ordered.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(Type::Decimal(38, 0, i128::MAX) > Type::Decimal(38, 38, i128::MAX));
+
+        assert_eq!(rescale_decimal(15, 1, 3), Some(1500));
+        assert_eq!(rescale_decimal(1500, 3, 1), Some(15));
+        assert_eq!(rescale_decimal(1501, 3, 1), None);
+        assert_eq!(rescale_decimal(i128::MAX, 0, 1), None);
+    }
+
+    #[test]
+    fn number_coercion() {
+        assert_eq!(
+            coerce_number(&Type::I32(-5), &Type::I64(0)),
+            Some(Type::I64(-5))
+        );
+        assert_eq!(coerce_number(&Type::I32(-5), &Type::U8(0)), None);
+        assert_eq!(coerce_number(&Type::I64(300), &Type::U8(0)), None);
+        assert_eq!(
+            coerce_number(&Type::I8(3), &Type::Decimal(5, 2, 0)),
+            Some(Type::Decimal(5, 2, 300))
+        );
+        assert_eq!(
+            coerce_number(&Type::I16(1000), &Type::Decimal(5, 2, 0)),
+            None
+        );
+        assert_eq!(
+            coerce_number(&Type::Decimal(10, 3, 2000), &Type::U16(0)),
+            Some(Type::U16(2))
+        );
+        assert_eq!(
+            coerce_number(&Type::Decimal(10, 3, 1500), &Type::U16(0)),
+            None
+        );
+        assert_eq!(
+            coerce_number(&Type::Decimal(10, 2, -125), &Type::F32(0.0)),
+            Some(Type::F32(-1.25))
+        );
+        assert_eq!(
+            coerce_number(&Type::F32(0.1), &Type::F64(0.0)),
+            Some(Type::F64(0.1))
+        );
+        assert_eq!(
+            coerce_number(&Type::F64(0.1), &Type::F32(0.0)),
+            Some(Type::F32(0.1))
+        );
+        assert_eq!(
+            coerce_number(&Type::F64(1e-10), &Type::F32(0.0)),
+            Some(Type::F32(1e-10))
+        );
+        assert_eq!(
+            coerce_number(&Type::F64(0.123456789), &Type::F32(0.0)),
+            None
+        );
+        assert_eq!(coerce_number(&Type::I32(16777217), &Type::F32(0.0)), None);
+        assert_eq!(
+            coerce_number(&Type::F64(2.5), &Type::Decimal(4, 1, 0)),
+            Some(Type::Decimal(4, 1, 25))
+        );
+        assert_eq!(coerce_number(&Type::F64(2.5), &Type::I32(0)), None);
+        assert_eq!(
+            coerce_number(&Type::F64(-3.0), &Type::I32(0)),
+            Some(Type::I32(-3))
+        );
+        assert_eq!(coerce_number(&Type::F64(f64::NAN), &Type::I32(0)), None);
+        assert_eq!(
+            coerce_number(&Type::U128(u128::MAX), &Type::U128(0)),
+            Some(Type::U128(u128::MAX))
+        );
+        assert_eq!(coerce_number(&Type::U128(u128::MAX), &Type::I128(0)), None);
+    }
+
+    #[test]
+    fn definition_serialization() {
+        for type_ in [
+            Type::I64(0),
+            Type::Varchar(42, String::new()),
+            Type::Decimal(12, 4, 0),
+            Type::Binary(32, Vec::new()),
+            Type::Bytes(16, Vec::new()),
+        ] {
+            let definition = type_.serialize_definition();
+            let deserialized = Type::deserialize_definition(&mut definition.as_slice()).unwrap();
+            assert_eq!(deserialized, type_);
+        }
+
+        let labels: Arc<[String]> = Arc::from([String::from("new"), String::from("паид")]);
+        let definition = Type::Enum(labels.clone(), 0).serialize_definition();
+        match Type::deserialize_definition(&mut definition.as_slice()) {
+            Ok(Type::Enum(deserialized, _)) => assert_eq!(deserialized, labels),
+            result => panic!("Unexpected definition {:?}", result),
+        }
+    }
+
+    #[test]
+    fn enum_values() {
+        let labels: Arc<[String]> = Arc::from(["shipped", "new", "paid"].map(String::from));
+        let other_labels: Arc<[String]> = Arc::from(["paid"].map(String::from));
+        assert_eq!(Type::Enum(labels.clone(), 2), Type::Enum(other_labels, 0));
+        assert!(Type::Enum(labels.clone(), 1) < Type::Enum(labels.clone(), 0));
+        assert!(Type::deserialize(&[3], &Type::Enum(labels, 0)).is_err());
+    }
+
+    #[test]
+    fn integer_values() {
+        assert_eq!(Type::U8(200).integer_value(), Some(200));
+        assert_eq!(Type::U128(u128::MAX).integer_value(), None);
+        assert_eq!(Type::F32(1.0).integer_value(), None);
+        assert_eq!(Type::I16(0).with_integer_value(-5), Some(Type::I16(-5)));
+        assert_eq!(Type::U8(0).with_integer_value(256), None);
+        assert_eq!(Type::U32(0).with_integer_value(-1), None);
+    }
+
     #[test]
     fn deserialize_errors() {
         let value = Type::I32(42);
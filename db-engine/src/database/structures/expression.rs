@@ -0,0 +1,258 @@
+use super::Type;
+use super::dbtype::{self, MAX_DECIMAL_PRECISION};
+use bincode::{Decode, Encode};
+
+/// Expression the value of a generated field is computed with from other fields of the
+/// same record. The result is null if any of the values it uses is null
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub enum Expression {
+    /// Value of a field of the record
+    Field(u16),
+    Value(Type),
+    Add(Box<Expression>, Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    /// Integers are divided without remainder, decimals are truncated to the larger scale
+    /// of the operands
+    Divide(Box<Expression>, Box<Expression>),
+    Lower(Box<Expression>),
+    Upper(Box<Expression>),
+    Concat(Box<Expression>, Box<Expression>),
+}
+
+/// Numeric value an arithmetic operation is done with
+enum Number {
+    Integer(i128),
+    /// Unscaled value and the scale
+    Decimal(i128, u8),
+    Float(f64),
+}
+
+impl Number {
+    fn from_type(value: &Type) -> Option<Number> {
+        if let Some(value) = value.integer_value() {
+            return Some(Number::Integer(value));
+        }
+        match value {
+            Type::Decimal(_, scale, value) => Some(Number::Decimal(*value, *scale)),
+            Type::F32(value) => Some(Number::Float(*value as f64)),
+            Type::F64(value) => Some(Number::Float(*value)),
+            _ => None,
+        }
+    }
+
+    fn as_float(&self) -> f64 {
+        match self {
+            Number::Integer(value) => *value as f64,
+            Number::Decimal(value, scale) => *value as f64 / 10f64.powi(*scale as i32),
+            Number::Float(value) => *value,
+        }
+    }
+
+    fn as_decimal(&self) -> (i128, u8) {
+        match self {
+            Number::Integer(value) => (*value, 0),
+            Number::Decimal(value, scale) => (*value, *scale),
+            Number::Float(_) => unreachable!(),
+        }
+    }
+}
+
+impl Expression {
+    /// Fields of the record the expression uses
+    pub fn fields(&self) -> Vec<u16> {
+        match self {
+            Expression::Field(field) => vec![*field],
+            Expression::Value(_) => Vec::new(),
+            Expression::Lower(value) | Expression::Upper(value) => value.fields(),
+            Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b)
+            | Expression::Concat(a, b) => {
+                let mut fields = a.fields();
+                fields.extend(b.fields());
+                fields
+            }
+        }
+    }
+
+    /// Computes the value for the record. Integers are given as i128, decimals with the
+    /// maximum precision and floats as f64, the value still has to be fit to the field
+    pub fn evaluate(&self, record: &[Type]) -> Result<Type, String> {
+        let (a, b) = match self {
+            Expression::Field(field) => return Ok(record[*field as usize].clone()),
+            Expression::Value(value) => return Ok(value.clone()),
+            Expression::Lower(value) | Expression::Upper(value) => {
+                let value = match value.evaluate(record)? {
+                    Type::Null => return Ok(Type::Null),
+                    Type::Varchar(_, value) | Type::Text(value) => value,
+                    value => return Err(format!("Cannot change case of {:?}", value)),
+                };
+                let value = match self {
+                    Expression::Lower(_) => value.to_lowercase(),
+                    _ => value.to_uppercase(),
+                };
+                return Ok(Type::Varchar(u8::MAX, value));
+            }
+            Expression::Add(a, b)
+            | Expression::Subtract(a, b)
+            | Expression::Multiply(a, b)
+            | Expression::Divide(a, b)
+            | Expression::Concat(a, b) => (a.evaluate(record)?, b.evaluate(record)?),
+        };
+        if a == Type::Null || b == Type::Null {
+            return Ok(Type::Null);
+        }
+        if let Expression::Concat(_, _) = self {
+            return match (a, b) {
+                (Type::Varchar(_, a) | Type::Text(a), Type::Varchar(_, b) | Type::Text(b)) => {
+                    Ok(Type::Varchar(u8::MAX, a + &b))
+                }
+                (a, b) => Err(format!("Cannot concatenate {:?} and {:?}", a, b)),
+            };
+        }
+        let (Some(x), Some(y)) = (Number::from_type(&a), Number::from_type(&b)) else {
+            return Err(format!("Cannot do arithmetic with {:?} and {:?}", a, b));
+        };
+        self.calculate(x, y)
+            .ok_or_else(|| format!("Cannot compute {:?} with {:?} and {:?}", self, a, b))
+    }
+
+    /// Does the arithmetic operation, None on overflow and division by zero
+    fn calculate(&self, x: Number, y: Number) -> Option<Type> {
+        if let (Number::Float(_), _) | (_, Number::Float(_)) = (&x, &y) {
+            let (x, y) = (x.as_float(), y.as_float());
+            return Some(Type::F64(match self {
+                Expression::Add(_, _) => x + y,
+                Expression::Subtract(_, _) => x - y,
+                Expression::Multiply(_, _) => x * y,
+                _ => x / y,
+            }));
+        }
+        if let (Number::Integer(x), Number::Integer(y)) = (&x, &y) {
+            return Some(Type::I128(match self {
+                Expression::Add(_, _) => x.checked_add(*y)?,
+                Expression::Subtract(_, _) => x.checked_sub(*y)?,
+                Expression::Multiply(_, _) => x.checked_mul(*y)?,
+                _ => x.checked_div(*y)?,
+            }));
+        }
+        let ((x, x_scale), (y, y_scale)) = (x.as_decimal(), y.as_decimal());
+        let scale = x_scale.max(y_scale);
+        let (value, scale) = match self {
+            Expression::Add(_, _) | Expression::Subtract(_, _) => {
+                let x = dbtype::rescale_decimal(x, x_scale, scale)?;
+                let y = dbtype::rescale_decimal(y, y_scale, scale)?;
+                match self {
+                    Expression::Add(_, _) => (x.checked_add(y)?, scale),
+                    _ => (x.checked_sub(y)?, scale),
+                }
+            }
+            Expression::Multiply(_, _) => (x.checked_mul(y)?, x_scale + y_scale),
+            _ => {
+                // x / y has the scale of x less the scale of y, so x is scaled up first
+                let x = dbtype::rescale_decimal(x, x_scale, scale + y_scale)?;
+                (x.checked_div(y)?, scale)
+            }
+        };
+        match scale <= MAX_DECIMAL_PRECISION {
+            true => Some(Type::Decimal(MAX_DECIMAL_PRECISION, scale, value)),
+            false => None,
+        }
+    }
+}
+
+/// Brings a computed value to the type of the field: integers and decimals become values of
+/// numeric fields if they fit, numbers become floats. Other values are left as they are
+pub fn fit(value: Type, type_: &Type) -> Option<Type> {
+    let Some(number) = Number::from_type(&value) else {
+        return Some(value);
+    };
+    match (number, type_) {
+        (number, Type::F32(_)) => Some(Type::F32(number.as_float() as f32)),
+        (number, Type::F64(_)) => Some(Type::F64(number.as_float())),
+        (Number::Integer(value), Type::Decimal(precision, _, _)) => {
+            Some(Type::Decimal(*precision, 0, value))
+        }
+        (Number::Integer(value), type_) => type_.with_integer_value(value),
+        (Number::Decimal(value, scale), type_) if type_.integer_value().is_some() => {
+            type_.with_integer_value(dbtype::rescale_decimal(value, scale, 0)?)
+        }
+        _ => Some(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(idx: u16) -> Box<Expression> {
+        Box::new(Expression::Field(idx))
+    }
+
+    #[test]
+    fn arithmetic() {
+        let record = [
+            Type::Decimal(10, 2, 1250),
+            Type::U16(3),
+            Type::I32(-7),
+            Type::F32(0.5),
+            Type::Null,
+        ];
+        let total = Expression::Multiply(field(0), field(1));
+        assert_eq!(
+            total.evaluate(&record),
+            Ok(Type::Decimal(MAX_DECIMAL_PRECISION, 2, 3750))
+        );
+        let sum = Expression::Add(field(1), field(2));
+        assert_eq!(sum.evaluate(&record), Ok(Type::I128(-4)));
+        let ratio = Expression::Divide(field(2), field(1));
+        assert_eq!(ratio.evaluate(&record), Ok(Type::I128(-2)));
+        let price = Expression::Divide(field(0), field(1));
+        assert_eq!(
+            price.evaluate(&record),
+            Ok(Type::Decimal(MAX_DECIMAL_PRECISION, 2, 416))
+        );
+        let half = Expression::Multiply(field(1), field(3));
+        assert_eq!(half.evaluate(&record), Ok(Type::F64(1.5)));
+        assert_eq!(
+            Expression::Add(field(1), field(4)).evaluate(&record),
+            Ok(Type::Null)
+        );
+        let zero = Box::new(Expression::Value(Type::I8(0)));
+        assert!(
+            Expression::Divide(field(1), zero)
+                .evaluate(&record)
+                .is_err()
+        );
+
+        assert_eq!(fit(Type::I128(-4), &Type::I64(0)), Some(Type::I64(-4)));
+        assert_eq!(fit(Type::I128(-4), &Type::U64(0)), None);
+        assert_eq!(
+            fit(Type::I128(2), &Type::Decimal(5, 2, 0)),
+            Some(Type::Decimal(5, 0, 2))
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let record = [
+            Type::Varchar(16, String::from("Anna@Mail.COM")),
+            Type::Text(String::from("!")),
+            Type::U8(1),
+        ];
+        let lower = Expression::Lower(field(0));
+        assert_eq!(
+            lower.evaluate(&record),
+            Ok(Type::Varchar(u8::MAX, String::from("anna@mail.com")))
+        );
+        let shout = Expression::Concat(Box::new(Expression::Upper(field(0))), field(1));
+        assert_eq!(
+            shout.evaluate(&record),
+            Ok(Type::Varchar(u8::MAX, String::from("ANNA@MAIL.COM!")))
+        );
+        assert!(Expression::Lower(field(2)).evaluate(&record).is_err());
+        assert_eq!(shout.fields(), vec![0, 1]);
+    }
+}
@@ -1,12 +1,50 @@
 use super::Type;
+use super::collation::Collation;
+use super::dbtype::{self, MAX_TZ_OFFSET_MINUTES, MICROS_PER_DAY};
+use super::expression::{self, Expression};
+use super::json;
 use byteorder::ReadBytesExt;
 use std::io::Read;
 
+/// Version of UUIDs generated for a field when a new record doesn't have its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UuidVersion {
+    /// Random
+    V4 = 4,
+    /// Ordered by the time of generation
+    V7 = 7,
+}
+
+impl UuidVersion {
+    pub fn generate(&self) -> Type {
+        let uuid = match self {
+            UuidVersion::V4 => uuid::Uuid::new_v4(),
+            UuidVersion::V7 => uuid::Uuid::now_v7(),
+        };
+        Type::Uuid(uuid.as_u128())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Field {
     pub name: String,
     pub type_: Type,
     pub nullable: bool,
+    /// Only makes sense for string and bytes fields: if set, too long values are cut to
+    /// the declared length, otherwise they are rejected
+    pub truncate: bool,
+    /// Only makes sense for integer fields: if set, new records that don't have the value
+    /// take the next value of the field's sequence
+    pub auto_increment: bool,
+    pub generate_uuid: Option<UuidVersion>,
+    /// Value of the field for new records that don't have it
+    pub default: Option<Type>,
+    /// Expression the value of the field is computed with. Such values are never set
+    /// directly, they are computed when a record is added and whenever the fields the
+    /// expression uses change
+    pub generated: Option<Expression>,
+    /// Only makes sense for string and text fields
+    pub collation: Collation,
 }
 
 impl Field {
@@ -16,11 +54,20 @@ impl Field {
         if length > u8::MAX.into() {
             panic!("Name of '{0}' field takes more than 255 bytes", self.name);
         }
-        let mut buffer: Vec<u8> = vec![0; length + 3];
-        buffer[0] = length as u8;
-        buffer[1..(length + 1)].copy_from_slice(self.name.as_bytes());
-        buffer[length + 1] = self.type_.type_id();
-        buffer[length + 2] = self.nullable.into();
+        let mut buffer: Vec<u8> = Vec::with_capacity(length + 5);
+        buffer.push(length as u8);
+        buffer.extend_from_slice(self.name.as_bytes());
+        buffer.extend_from_slice(&self.type_.serialize_definition());
+        buffer.push(self.nullable.into());
+        buffer.push(self.truncate.into());
+        buffer.push(self.auto_increment.into());
+        buffer.push(self.generate_uuid.map_or(0, |version| version as u8));
+        let config = bincode::config::standard().with_little_endian();
+        bincode::encode_into_std_write(&self.default, &mut buffer, config)
+            .expect("Writing to a vector cannot fail");
+        bincode::encode_into_std_write(&self.generated, &mut buffer, config)
+            .expect("Writing to a vector cannot fail");
+        buffer.push(self.collation as u8);
         buffer
     }
 
@@ -32,13 +79,426 @@ impl Field {
             .map_err(|_| "Incomplete field name")?;
         let name = String::from_utf8(name_buf).map_err(|_| "Invalid UTF-8 in field name")?;
 
-        let type_id = rdr.read_u8().map_err(|_| "Missing type id")?;
+        let type_ = Type::deserialize_definition(rdr)?;
         let nullable = rdr.read_u8().map_err(|_| "Missing nullable flag")? != 0;
+        let truncate = rdr.read_u8().map_err(|_| "Missing truncate flag")? != 0;
+        let auto_increment = rdr.read_u8().map_err(|_| "Missing auto increment flag")? != 0;
+        let generate_uuid = match rdr.read_u8().map_err(|_| "Missing uuid version")? {
+            0 => None,
+            4 => Some(UuidVersion::V4),
+            7 => Some(UuidVersion::V7),
+            _ => return Err("Unknown uuid version"),
+        };
+        let config = bincode::config::standard().with_little_endian();
+        let default =
+            bincode::decode_from_std_read(rdr, config).map_err(|_| "Corrupted default value")?;
+        let generated =
+            bincode::decode_from_std_read(rdr, config).map_err(|_| "Corrupted generated value")?;
+        let collation = Collation::from_id(rdr.read_u8().map_err(|_| "Missing collation")?)?;
 
         Ok(Self {
             name,
-            type_: Type::from_type_id(type_id),
+            type_,
             nullable,
+            truncate,
+            auto_increment,
+            generate_uuid,
+            default,
+            generated,
+            collation,
         })
     }
+
+    /// Value of the field for a new record that doesn't have it: a generated one, the default
+    /// one or null, in that order. Auto increment values are taken from the sequence before
+    pub fn missing_value(&self) -> Result<Type, String> {
+        if let Some(version) = self.generate_uuid {
+            return Ok(version.generate());
+        }
+        if let Some(default) = &self.default {
+            return Ok(default.clone());
+        }
+        match self.nullable {
+            true => Ok(Type::Null),
+            false => Err(format!("Value of '{}' field is missing", self.name)),
+        }
+    }
+
+    /// Computes the value of a generated field for the record
+    pub fn compute_value(&self, record: &[Type]) -> Result<Type, String> {
+        let Some(expression) = &self.generated else {
+            return Err(format!("Field '{}' is not generated", self.name));
+        };
+        let value = match expression.evaluate(record) {
+            Ok(value) => value,
+            Err(e) => return Err(format!("{} in '{}' field", e, self.name)),
+        };
+        match expression::fit(value, &self.type_) {
+            Some(value) => self.prepare_value(value),
+            None => Err(format!(
+                "Computed value doesn't fit in '{}' field",
+                self.name
+            )),
+        }
+    }
+
+    /// Brings a value the field is compared with to the type of the field the same way
+    /// as stored values, but without checks of length and nullability
+    pub fn prepare_operand(&self, value: Type) -> Result<Type, String> {
+        if value == Type::Null {
+            return Ok(Type::Null);
+        }
+        match self.convert_value(value)? {
+            value @ Type::Decimal(_, _, _) => self.coerce_number(value),
+            value => Ok(value),
+        }
+    }
+
+    /// Converts a number to the type of the field, fails if it's not a number or it
+    /// would lose digits or range
+    fn coerce_number(&self, value: Type) -> Result<Type, String> {
+        match dbtype::coerce_number(&value, &self.type_) {
+            Some(value) => Ok(value),
+            None if !value.is_number() => Err(format!(
+                "Value {:?} doesn't match the type of '{}' field",
+                value, self.name
+            )),
+            None => Err(format!(
+                "Value {:?} cannot be converted to the type of '{}' field without loss",
+                value, self.name
+            )),
+        }
+    }
+
+    /// Checks that the value can be stored in the field and brings it to the form
+    /// defined by the field (e.g. sets declared length of varchar)
+    pub fn prepare_value(&self, value: Type) -> Result<Type, String> {
+        if value == Type::Null {
+            return match self.nullable {
+                true => Ok(Type::Null),
+                false => Err(format!("Field '{}' cannot be null", self.name)),
+            };
+        }
+        match (&self.type_, self.convert_value(value)?) {
+            (Type::Varchar(len, _), Type::Varchar(_, mut data)) => {
+                let len = *len as usize;
+                if data.len() > len {
+                    if !self.truncate {
+                        return Err(format!(
+                            "String '{}' is longer than {} bytes allowed in '{}' field",
+                            data, len, self.name
+                        ));
+                    }
+                    let mut end = len;
+                    while !data.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    data.truncate(end);
+                }
+                Ok(Type::Varchar(len as u8, data))
+            }
+            (Type::Binary(len, _), Type::Binary(_, data)) => {
+                if data.len() != *len as usize {
+                    return Err(format!(
+                        "'{}' field requires exactly {} bytes, but {} were given",
+                        self.name,
+                        len,
+                        data.len()
+                    ));
+                }
+                Ok(Type::Binary(*len, data))
+            }
+            (Type::Bytes(len, _), Type::Bytes(_, mut data)) => {
+                if data.len() > *len as usize {
+                    if !self.truncate {
+                        return Err(format!(
+                            "{} bytes are more than {} bytes allowed in '{}' field",
+                            data.len(),
+                            len,
+                            self.name
+                        ));
+                    }
+                    data.truncate(*len as usize);
+                }
+                Ok(Type::Bytes(*len, data))
+            }
+            (Type::Decimal(precision, scale, _), Type::Decimal(_, value_scale, value)) => {
+                let value = dbtype::rescale_decimal(value, value_scale, *scale)
+                    .filter(|value| value.unsigned_abs() < 10u128.pow(*precision as u32));
+                match value {
+                    Some(value) => Ok(Type::Decimal(*precision, *scale, value)),
+                    None => Err(format!(
+                        "Decimal cannot be stored in '{}' field with precision {} and scale {} without losing digits",
+                        self.name, precision, scale
+                    )),
+                }
+            }
+            (_, Type::Time(time)) if time >= MICROS_PER_DAY => Err(format!(
+                "Time {} is out of day range in '{}' field",
+                time, self.name
+            )),
+            (_, Type::TimestampTz(_, offset)) if offset.abs() > MAX_TZ_OFFSET_MINUTES => {
+                Err(format!(
+                    "Time zone offset {} minutes is out of range in '{}' field",
+                    offset, self.name
+                ))
+            }
+            (_, value) => Ok(value),
+        }
+    }
+
+    /// Brings a value to the type of the field, fails if it's of another type
+    fn convert_value(&self, value: Type) -> Result<Type, String> {
+        // Strings come as varchars and bytes come as blobs, they take the form of the field.
+        // Enum values come as their labels and json documents come as their text
+        let value = match (&self.type_, value) {
+            (Type::Text(_), Type::Varchar(_, data)) => Type::Text(data),
+            (Type::Json(_), Type::Varchar(_, text) | Type::Text(text)) => {
+                match json::parse(&text) {
+                    Ok(data) => Type::Json(data),
+                    Err(e) => return Err(format!("{} in '{}' field", e, self.name)),
+                }
+            }
+            (Type::Enum(labels, _), Type::Varchar(_, label)) => {
+                match labels.iter().position(|field_label| *field_label == label) {
+                    Some(ordinal) => Type::Enum(labels.clone(), ordinal as u8),
+                    None => {
+                        return Err(format!(
+                            "'{}' is not one of the labels of '{}' field",
+                            label, self.name
+                        ));
+                    }
+                }
+            }
+            (Type::Binary(len, _), Type::Blob(data)) => Type::Binary(*len, data),
+            (Type::Bytes(len, _), Type::Blob(data)) => Type::Bytes(*len, data),
+            // Numbers of other types are converted to the type of the field
+            (type_, value) if type_.is_number() && value.type_id() != type_.type_id() => {
+                self.coerce_number(value)?
+            }
+            (_, value) => value,
+        };
+        if value.type_id() != self.type_.type_id() {
+            return Err(format!(
+                "Value {:?} doesn't match the type of '{}' field",
+                value, self.name
+            ));
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varchar_field(len: u8, truncate: bool) -> Field {
+        Field {
+            name: String::from("name"),
+            type_: Type::Varchar(len, String::new()),
+            nullable: false,
+            truncate,
+            auto_increment: false,
+            generate_uuid: None,
+            default: None,
+            generated: None,
+            collation: Collation::Binary,
+        }
+    }
+
+    #[test]
+    fn serialization_deserialization() {
+        let mut uuid_field = varchar_field(0, false);
+        uuid_field.type_ = Type::Uuid(0);
+        uuid_field.generate_uuid = Some(UuidVersion::V7);
+        let mut default_field = varchar_field(16, false);
+        default_field.default = Some(Type::Varchar(16, String::from("unknown")));
+        let mut id_field = varchar_field(0, false);
+        id_field.type_ = Type::U64(0);
+        id_field.auto_increment = true;
+        let mut email_field = varchar_field(32, false);
+        email_field.generated = Some(Expression::Lower(Box::new(Expression::Field(0))));
+        email_field.collation = Collation::Unicode;
+        for field in [
+            varchar_field(32, true),
+            uuid_field,
+            default_field,
+            id_field,
+            email_field,
+        ] {
+            let serialized = field.serialize();
+            assert_eq!(Field::deserialize(&mut serialized.as_slice()), Ok(field));
+        }
+    }
+
+    #[test]
+    fn uuid_generation() {
+        let Type::Uuid(first) = UuidVersion::V7.generate() else {
+            panic!("Not a uuid");
+        };
+        let Type::Uuid(second) = UuidVersion::V7.generate() else {
+            panic!("Not a uuid");
+        };
+        assert!(first < second);
+        assert_ne!(UuidVersion::V4.generate(), UuidVersion::V4.generate());
+    }
+
+    #[test]
+    fn too_long_strings() {
+        let value = Type::Varchar(u8::MAX, String::from("Привет"));
+        assert!(
+            varchar_field(5, false)
+                .prepare_value(value.clone())
+                .is_err()
+        );
+        assert_eq!(
+            varchar_field(5, true).prepare_value(value),
+            Ok(Type::Varchar(5, String::from("Пр")))
+        );
+        assert_eq!(
+            varchar_field(10, false).prepare_value(Type::Varchar(2, String::from("ab"))),
+            Ok(Type::Varchar(10, String::from("ab")))
+        );
+    }
+
+    #[test]
+    fn temporal_ranges() {
+        let mut field = varchar_field(0, false);
+        field.type_ = Type::Time(0);
+        assert!(field.prepare_value(Type::Time(MICROS_PER_DAY - 1)).is_ok());
+        assert!(field.prepare_value(Type::Time(MICROS_PER_DAY)).is_err());
+        field.type_ = Type::TimestampTz(0, 0);
+        assert!(
+            field
+                .prepare_value(Type::TimestampTz(0, -MAX_TZ_OFFSET_MINUTES))
+                .is_ok()
+        );
+        assert!(field.prepare_value(Type::TimestampTz(0, 19 * 60)).is_err());
+    }
+
+    #[test]
+    fn decimal_precision() {
+        let mut field = varchar_field(0, false);
+        field.type_ = Type::Decimal(5, 2, 0);
+        assert_eq!(
+            field.prepare_value(Type::Decimal(38, 1, 15)),
+            Ok(Type::Decimal(5, 2, 150))
+        );
+        assert_eq!(
+            field.prepare_value(Type::Decimal(38, 3, -99990)),
+            Ok(Type::Decimal(5, 2, -9999))
+        );
+        assert!(field.prepare_value(Type::Decimal(38, 3, 1001)).is_err());
+        assert!(field.prepare_value(Type::Decimal(38, 0, 1000)).is_err());
+    }
+
+    #[test]
+    fn number_coercion() {
+        let mut field = varchar_field(0, false);
+        field.type_ = Type::I64(0);
+        assert_eq!(field.prepare_value(Type::I32(-7)), Ok(Type::I64(-7)));
+        assert_eq!(field.prepare_value(Type::U64(7)), Ok(Type::I64(7)));
+        assert!(field.prepare_value(Type::U64(u64::MAX)).is_err());
+        assert!(field.prepare_value(Type::F64(0.5)).is_err());
+        assert!(
+            field
+                .prepare_value(Type::Varchar(1, String::from("7")))
+                .is_err()
+        );
+
+        field.type_ = Type::Decimal(5, 2, 0);
+        assert_eq!(
+            field.prepare_operand(Type::I8(3)),
+            Ok(Type::Decimal(5, 2, 300))
+        );
+        assert_eq!(
+            field.prepare_operand(Type::Decimal(38, 1, 15)),
+            Ok(Type::Decimal(5, 2, 150))
+        );
+        assert!(field.prepare_operand(Type::Decimal(38, 3, 1001)).is_err());
+        assert_eq!(field.prepare_operand(Type::Null), Ok(Type::Null));
+    }
+
+    #[test]
+    fn strings_to_text() {
+        let mut field = varchar_field(0, false);
+        field.type_ = Type::Text(String::new());
+        let text = "a".repeat(1000);
+        assert_eq!(
+            field.prepare_value(Type::Varchar(u8::MAX, text.clone())),
+            Ok(Type::Text(text))
+        );
+    }
+
+    #[test]
+    fn bytes_length() {
+        let mut field = varchar_field(0, false);
+        field.type_ = Type::Binary(4, Vec::new());
+        let hash = vec![0xff, 0xfe, 0x00, 0x80];
+        assert_eq!(
+            field.prepare_value(Type::Blob(hash.clone())),
+            Ok(Type::Binary(4, hash.clone()))
+        );
+        assert!(field.prepare_value(Type::Blob(vec![0xff])).is_err());
+
+        field.type_ = Type::Bytes(2, Vec::new());
+        assert!(field.prepare_value(Type::Blob(hash.clone())).is_err());
+        field.truncate = true;
+        assert_eq!(
+            field.prepare_value(Type::Blob(hash)),
+            Ok(Type::Bytes(2, vec![0xff, 0xfe]))
+        );
+    }
+
+    #[test]
+    fn missing_values() {
+        let mut field = varchar_field(8, false);
+        assert!(field.missing_value().is_err());
+        field.nullable = true;
+        assert_eq!(field.missing_value(), Ok(Type::Null));
+        field.default = Some(Type::Varchar(8, String::from("new")));
+        assert_eq!(
+            field.missing_value(),
+            Ok(Type::Varchar(8, String::from("new")))
+        );
+    }
+
+    #[test]
+    fn enum_labels() {
+        let mut field = varchar_field(0, false);
+        field.type_ = Type::Enum(["new", "paid"].map(String::from).into(), 0);
+        assert!(matches!(
+            field.prepare_value(Type::Varchar(4, String::from("paid"))),
+            Ok(Type::Enum(_, 1))
+        ));
+        assert!(
+            field
+                .prepare_value(Type::Varchar(7, String::from("shipped")))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn json_documents() {
+        let mut field = varchar_field(0, false);
+        field.type_ = Type::Json(Vec::new());
+        let text = String::from(r#"{"user": {"id": 5}}"#);
+        assert_eq!(
+            field.prepare_value(Type::Varchar(u8::MAX, text.clone())),
+            Ok(Type::Json(json::parse(&text).unwrap()))
+        );
+        assert!(
+            field
+                .prepare_value(Type::Varchar(u8::MAX, String::from("{id: 5}")))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn null_values() {
+        let mut field = varchar_field(10, false);
+        assert!(field.prepare_value(Type::Null).is_err());
+        field.nullable = true;
+        assert_eq!(field.prepare_value(Type::Null), Ok(Type::Null));
+    }
 }
@@ -1,5 +1,7 @@
-use super::Field;
+use super::{Collation, Type};
+use std::cmp::Ordering;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Filter {
     Equal,
     Less,
@@ -9,24 +11,312 @@ pub enum Filter {
     Contains,
     StartsWith,
     EndsWith,
+    IsNull,
+    IsNotNull,
+    /// Json document has a value at the path
+    HasKey,
+    NotEqual,
+    /// Value is one of the listed values
+    In,
+    /// Value is none of the listed values
+    NotIn,
+    /// Value is from the lower to the upper bound, both included
+    Between,
+}
+
+impl Filter {
+    pub fn from_id(id: u8) -> Result<Self, &'static str> {
+        Ok(match id {
+            0 => Filter::Equal,
+            1 => Filter::Less,
+            2 => Filter::Greater,
+            3 => Filter::LessEq,
+            4 => Filter::GreaterEq,
+            5 => Filter::Contains,
+            6 => Filter::StartsWith,
+            7 => Filter::EndsWith,
+            8 => Filter::IsNull,
+            9 => Filter::IsNotNull,
+            10 => Filter::HasKey,
+            11 => Filter::NotEqual,
+            12 => Filter::In,
+            13 => Filter::NotIn,
+            14 => Filter::Between,
+            _ => return Err("Unknown filter"),
+        })
+    }
+
+    /// Whether the filter compares values with an operand rather than checking nulls or keys
+    pub fn compares(&self) -> bool {
+        !matches!(self, Filter::IsNull | Filter::IsNotNull | Filter::HasKey)
+    }
+
+    /// Whether the filter takes a list of values rather than a single one
+    pub fn takes_list(&self) -> bool {
+        matches!(self, Filter::In | Filter::NotIn | Filter::Between)
+    }
+
+    /// Whether the filter depends on the order of values rather than on their equality
+    pub fn orders(&self) -> bool {
+        matches!(
+            self,
+            Filter::Less | Filter::Greater | Filter::LessEq | Filter::GreaterEq | Filter::Between
+        )
+    }
+
+    /// Whether the filter looks for a substring and so applies only to strings
+    pub fn is_substring(&self) -> bool {
+        matches!(
+            self,
+            Filter::Contains | Filter::StartsWith | Filter::EndsWith
+        )
+    }
+
+    /// Whether the value matches the filter with the operand, strings are compared by the
+    /// collation. Nulls match nothing but 'is null'
+    pub fn matches(&self, value: &Type, operand: &Type, collation: Collation) -> bool {
+        match self {
+            Filter::IsNull => *value == Type::Null,
+            Filter::IsNotNull => *value != Type::Null,
+            _ if *value == Type::Null || *operand == Type::Null => false,
+            Filter::Contains | Filter::StartsWith | Filter::EndsWith => {
+                let (
+                    Type::Varchar(_, value) | Type::Text(value),
+                    Type::Varchar(_, operand) | Type::Text(operand),
+                ) = (value, operand)
+                else {
+                    return false;
+                };
+                self.matches_str(&collation.fold(value), &collation.fold(operand))
+            }
+            Filter::HasKey => false,
+            _ => self.accepts(collation.compare(value, operand)),
+        }
+    }
+
+    /// Whether the value matches a list filter with the operands, which are the listed
+    /// values for 'in' and 'not in' and the bounds for 'between'. Nulls match nothing
+    pub fn matches_list(&self, value: &Type, operands: &[Type], collation: Collation) -> bool {
+        let listed = || {
+            operands
+                .iter()
+                .any(|operand| Filter::Equal.matches(value, operand, collation))
+        };
+        match (self, operands) {
+            _ if *value == Type::Null => false,
+            (Filter::In, _) => listed(),
+            (Filter::NotIn, _) => !listed(),
+            (Filter::Between, [lower, upper]) => {
+                Filter::GreaterEq.matches(value, lower, collation)
+                    && Filter::LessEq.matches(value, upper, collation)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a value ordered this way relative to the operand meets the comparison
+    pub fn accepts(&self, ordering: Ordering) -> bool {
+        match self {
+            Filter::Equal => ordering == Ordering::Equal,
+            Filter::NotEqual => ordering != Ordering::Equal,
+            Filter::Less => ordering == Ordering::Less,
+            Filter::Greater => ordering == Ordering::Greater,
+            Filter::LessEq => ordering != Ordering::Greater,
+            Filter::GreaterEq => ordering != Ordering::Less,
+            _ => false,
+        }
+    }
+
+    /// Whether the string matches a substring filter with the operand
+    pub fn matches_str(&self, value: &str, operand: &str) -> bool {
+        match self {
+            Filter::Contains => value.contains(operand),
+            Filter::StartsWith => value.starts_with(operand),
+            Filter::EndsWith => value.ends_with(operand),
+            _ => false,
+        }
+    }
 }
 
 pub struct FilterOption {
-    field: Field,
+    /// Name of the field the filter is applied to
+    column: String,
     filter: Filter,
+    /// Keys leading to the value of a json document the filter is applied to. If empty,
+    /// the filter is applied to the whole value of the field
+    json_path: Vec<String>,
+    /// Value the field is compared with, null for filters that don't compare or take a list
+    value: Type,
+    /// Values of 'in' and 'not in', the lower and the upper bounds of 'between'
+    values: Vec<Type>,
 }
 
 impl FilterOption {
-    pub fn new(field: Field, filter: Filter) -> Result<Self, String> {
-        // TODO: add validation
-        Ok(FilterOption { field, filter })
+    pub fn new(
+        column: String,
+        filter: Filter,
+        json_path: Vec<String>,
+        value: Type,
+        values: Vec<Type>,
+    ) -> Result<Self, String> {
+        if matches!(filter, Filter::HasKey) && json_path.is_empty() {
+            return Err(String::from("Key existence filter requires a json path."));
+        }
+        if filter.takes_list() {
+            if value != Type::Null {
+                return Err(format!(
+                    "Filter {:?} compares '{}' field with a list of values, not a single one.",
+                    filter, column
+                ));
+            }
+            if filter == Filter::Between && values.len() != 2 {
+                return Err(format!(
+                    "Filter {:?} on '{}' field takes exactly two values, the lower and the \
+                     upper bounds.",
+                    filter, column
+                ));
+            }
+            if values.contains(&Type::Null) {
+                return Err(format!(
+                    "Values to compare '{}' field with contain a null, nulls are found with \
+                     'is null' filter.",
+                    column
+                ));
+            }
+        } else if !values.is_empty() {
+            return Err(format!(
+                "Filter {:?} compares '{}' field with a single value, not a list.",
+                filter, column
+            ));
+        } else if !filter.compares() && value != Type::Null {
+            return Err(format!(
+                "Filter {:?} on '{}' field doesn't take a value.",
+                filter, column
+            ));
+        } else if filter.compares() && value == Type::Null {
+            return Err(format!(
+                "Value to compare '{}' field with is not specified, nulls are found with \
+                 'is null' filter.",
+                column
+            ));
+        }
+        Ok(FilterOption {
+            column,
+            filter,
+            json_path,
+            value,
+            values,
+        })
     }
 
-    pub fn field(&self) -> &Field {
-        &self.field
+    pub fn column(&self) -> &str {
+        &self.column
     }
 
     pub fn filter(&self) -> &Filter {
         &self.filter
     }
+
+    pub fn json_path(&self) -> &[String] {
+        &self.json_path
+    }
+
+    pub fn value(&self) -> &Type {
+        &self.value
+    }
+
+    pub fn values(&self) -> &[Type] {
+        &self.values
+    }
+}
+
+/// Condition on records made of filters joined with 'and', 'or' and 'not'
+pub enum FilterExpression {
+    Filter(FilterOption),
+    /// Matches all records if empty
+    And(Vec<FilterExpression>),
+    /// Matches no records if empty
+    Or(Vec<FilterExpression>),
+    /// Matches records the expression doesn't match, including the ones it doesn't match
+    /// because of nulls
+    Not(Box<FilterExpression>),
+}
+
+impl FilterExpression {
+    /// Expression that matches all records
+    pub fn all() -> Self {
+        FilterExpression::And(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        let (one, two) = (Type::I32(1), Type::I32(2));
+        let binary = Collation::Binary;
+        assert!(Filter::Less.matches(&one, &two, binary));
+        assert!(!Filter::Greater.matches(&one, &two, binary));
+        assert!(Filter::LessEq.matches(&two, &two, binary));
+        assert!(Filter::GreaterEq.matches(&two, &one, binary));
+        assert!(!Filter::Less.matches(&Type::Null, &two, binary));
+        assert!(Filter::IsNull.matches(&Type::Null, &Type::Null, binary));
+
+        let name = Type::Text(String::from("Anna Smith"));
+        let part = |part: &str| Type::Varchar(8, String::from(part));
+        assert!(Filter::StartsWith.matches(&name, &part("Anna"), binary));
+        assert!(!Filter::EndsWith.matches(&name, &part("SMITH"), binary));
+        assert!(Filter::EndsWith.matches(&name, &part("SMITH"), Collation::CaseInsensitive));
+        assert!(Filter::Contains.matches(&name, &part("a S"), binary));
+        assert!(!Filter::Contains.matches(&one, &part("1"), binary));
+        assert!(Filter::NotEqual.matches(&one, &two, binary));
+        assert!(!Filter::NotEqual.matches(&Type::Null, &two, binary));
+
+        let three = Type::I32(3);
+        let list = [one.clone(), three.clone()];
+        assert!(Filter::In.matches_list(&three, &list, binary));
+        assert!(!Filter::In.matches_list(&two, &list, binary));
+        assert!(Filter::NotIn.matches_list(&two, &list, binary));
+        assert!(!Filter::NotIn.matches_list(&Type::Null, &list, binary));
+        assert!(!Filter::In.matches_list(&one, &[], binary));
+        assert!(Filter::Between.matches_list(&two, &list, binary));
+        assert!(Filter::Between.matches_list(&three, &list, binary));
+        assert!(!Filter::Between.matches_list(&Type::I32(4), &list, binary));
+        let names = [part("ANNA"), part("bob")];
+        assert!(Filter::In.matches_list(&part("anna"), &names, Collation::CaseInsensitive));
+    }
+
+    #[test]
+    fn validation() {
+        let option = |filter, json_path: &[&str], value| {
+            let json_path = json_path.iter().map(|key| key.to_string()).collect();
+            FilterOption::new(String::from("field"), filter, json_path, value, Vec::new())
+        };
+        let list = |filter, values: &[Type]| {
+            FilterOption::new(
+                String::from("field"),
+                filter,
+                Vec::new(),
+                Type::Null,
+                values.to_vec(),
+            )
+        };
+        assert!(option(Filter::Equal, &[], Type::I32(0)).is_ok());
+        assert!(option(Filter::Less, &[], Type::Null).is_err());
+        assert!(option(Filter::IsNull, &[], Type::Null).is_ok());
+        assert!(option(Filter::IsNull, &[], Type::I32(5)).is_err());
+        assert!(option(Filter::IsNotNull, &[], Type::I32(5)).is_err());
+        assert!(option(Filter::HasKey, &["key"], Type::I32(5)).is_err());
+        assert!(option(Filter::HasKey, &[], Type::Null).is_err());
+        assert!(option(Filter::HasKey, &["key"], Type::Null).is_ok());
+        assert!(list(Filter::In, &[Type::I32(0), Type::I32(1)]).is_ok());
+        assert!(list(Filter::NotIn, &[]).is_ok());
+        assert!(list(Filter::In, &[Type::I32(0), Type::Null]).is_err());
+        assert!(list(Filter::Between, &[Type::I32(0), Type::I32(1)]).is_ok());
+        assert!(list(Filter::Between, &[Type::I32(0)]).is_err());
+        assert!(list(Filter::Equal, &[Type::I32(0)]).is_err());
+        assert!(option(Filter::In, &[], Type::I32(0)).is_err());
+    }
 }
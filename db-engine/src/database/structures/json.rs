@@ -0,0 +1,252 @@
+use super::Type;
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+
+// Tags of json values in the binary form. Strings, arrays and objects are followed by
+// their length, objects store keys in sorted order
+const NULL: u8 = 0;
+const FALSE: u8 = 1;
+const TRUE: u8 = 2;
+const INT: u8 = 3;
+const UINT: u8 = 4;
+const FLOAT: u8 = 5;
+const STRING: u8 = 6;
+const ARRAY: u8 = 7;
+const OBJECT: u8 = 8;
+
+/// Validates a json document and converts it to the binary form
+pub fn parse(text: &str) -> Result<Vec<u8>, String> {
+    match serde_json::from_str::<Value>(text) {
+        Ok(value) => Ok(encode(&value)),
+        Err(e) => Err(format!("Invalid json: {}", e)),
+    }
+}
+
+/// Converts a document in the binary form back to text
+pub fn to_string(data: &[u8]) -> Result<String, &'static str> {
+    Ok(decode(data)?.to_string())
+}
+
+pub fn encode(value: &Value) -> Vec<u8> {
+    let mut result = Vec::new();
+    encode_into(value, &mut result);
+    result
+}
+
+fn encode_into(value: &Value, buffer: &mut Vec<u8>) {
+    match value {
+        Value::Null => buffer.push(NULL),
+        Value::Bool(false) => buffer.push(FALSE),
+        Value::Bool(true) => buffer.push(TRUE),
+        Value::Number(number) => {
+            if let Some(number) = number.as_i64() {
+                buffer.push(INT);
+                buffer.extend_from_slice(&number.to_le_bytes());
+            } else if let Some(number) = number.as_u64() {
+                buffer.push(UINT);
+                buffer.extend_from_slice(&number.to_le_bytes());
+            } else {
+                buffer.push(FLOAT);
+                let number = number.as_f64().unwrap_or_default();
+                buffer.extend_from_slice(&number.to_le_bytes());
+            }
+        }
+        Value::String(string) => {
+            buffer.push(STRING);
+            encode_str(string, buffer);
+        }
+        Value::Array(array) => {
+            buffer.push(ARRAY);
+            buffer.extend_from_slice(&(array.len() as u32).to_le_bytes());
+            for item in array {
+                encode_into(item, buffer);
+            }
+        }
+        Value::Object(object) => {
+            buffer.push(OBJECT);
+            buffer.extend_from_slice(&(object.len() as u32).to_le_bytes());
+            for (key, item) in object {
+                encode_str(key, buffer);
+                encode_into(item, buffer);
+            }
+        }
+    }
+}
+
+fn encode_str(string: &str, buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(string.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(string.as_bytes());
+}
+
+pub fn decode(data: &[u8]) -> Result<Value, &'static str> {
+    let mut data = data;
+    let value = decode_from(&mut data)?;
+    match data.is_empty() {
+        true => Ok(value),
+        false => Err("Json document has trailing bytes."),
+    }
+}
+
+fn decode_from(data: &mut &[u8]) -> Result<Value, &'static str> {
+    let tag = take(data, 1)?[0];
+    Ok(match tag {
+        NULL => Value::Null,
+        FALSE => Value::Bool(false),
+        TRUE => Value::Bool(true),
+        INT => Value::from(i64::from_le_bytes(take(data, 8)?.try_into().unwrap())),
+        UINT => Value::from(u64::from_le_bytes(take(data, 8)?.try_into().unwrap())),
+        FLOAT => {
+            let number = f64::from_le_bytes(take(data, 8)?.try_into().unwrap());
+            match Number::from_f64(number) {
+                Some(number) => Value::Number(number),
+                None => return Err("Json number is not finite."),
+            }
+        }
+        STRING => Value::String(decode_str(data)?),
+        ARRAY => {
+            let len = decode_len(data)?;
+            let mut array = Vec::new();
+            for _ in 0..len {
+                array.push(decode_from(data)?);
+            }
+            Value::Array(array)
+        }
+        OBJECT => {
+            let len = decode_len(data)?;
+            let mut object = Map::new();
+            for _ in 0..len {
+                let key = decode_str(data)?;
+                object.insert(key, decode_from(data)?);
+            }
+            Value::Object(object)
+        }
+        _ => return Err("Unknown tag of a json value."),
+    })
+}
+
+fn decode_len(data: &mut &[u8]) -> Result<usize, &'static str> {
+    Ok(u32::from_le_bytes(take(data, 4)?.try_into().unwrap()) as usize)
+}
+
+fn decode_str(data: &mut &[u8]) -> Result<String, &'static str> {
+    let len = decode_len(data)?;
+    match std::str::from_utf8(take(data, len)?) {
+        Ok(string) => Ok(string.to_string()),
+        Err(_) => Err("Json string contains invalid UTF-8."),
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], &'static str> {
+    if data.len() < len {
+        return Err("Json document is incomplete.");
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Ok(head)
+}
+
+/// Value at the path of keys. Arrays are indexed by keys that are numbers
+pub fn get<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| match value {
+        Value::Object(object) => object.get(key),
+        Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Json form of a value a document is compared with. None if the value has no such form
+pub fn from_type(value: &Type) -> Option<Value> {
+    if let Some(number) = value.integer_value() {
+        return match i64::try_from(number) {
+            Ok(number) => Some(Value::from(number)),
+            Err(_) => u64::try_from(number).ok().map(Value::from),
+        };
+    }
+    match value {
+        Type::Null => Some(Value::Null),
+        Type::Boolean(var) => Some(Value::Bool(*var)),
+        Type::F32(var) => Number::from_f64(*var as f64).map(Value::Number),
+        Type::F64(var) => Number::from_f64(*var).map(Value::Number),
+        Type::Varchar(_, var) | Type::Text(var) => Some(Value::String(var.clone())),
+        Type::Json(data) => decode(data).ok(),
+        _ => None,
+    }
+}
+
+/// Compares json values, numbers are equal if they have the same value whatever their form
+pub fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => match (a.is_f64(), b.is_f64()) {
+            (false, false) => a == b,
+            _ => a.as_f64() == b.as_f64(),
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|((key_a, a), (key_b, b))| key_a == key_b && equal(a, b))
+        }
+        (a, b) => a == b,
+    }
+}
+
+/// Orders numbers by their value, strings and booleans by their own order. Other values
+/// and values of different kinds are not ordered
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let integer = |number: &Number| {
+                (number.as_i64().map(i128::from)).or(number.as_u64().map(i128::from))
+            };
+            match (integer(a), integer(b)) {
+                (Some(a), Some(b)) => Some(a.cmp(&b)),
+                _ => a.as_f64()?.partial_cmp(&b.as_f64()?),
+            }
+        }
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binary_form_round_trip() {
+        let text = r#"{"user":{"id":5,"tags":["a","б"]},"score":-1.5,"big":18446744073709551615,"ok":true,"none":null}"#;
+        let data = parse(text).unwrap();
+        let expected: Value = serde_json::from_str(text).unwrap();
+        assert_eq!(decode(&data), Ok(expected));
+        assert!(parse("{\"user\": ").is_err());
+        assert!(decode(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn paths() {
+        let value: Value = serde_json::from_str(r#"{"user":{"id":5,"tags":["a","b"]}}"#).unwrap();
+        let path = |keys: &[&str]| keys.iter().map(|key| key.to_string()).collect::<Vec<_>>();
+        assert_eq!(get(&value, &path(&["user", "id"])), Some(&Value::from(5)));
+        assert_eq!(
+            get(&value, &path(&["user", "tags", "1"])),
+            Some(&Value::from("b"))
+        );
+        assert_eq!(get(&value, &path(&["user", "name"])), None);
+        assert_eq!(get(&value, &path(&["user", "id", "x"])), None);
+    }
+
+    #[test]
+    fn number_equality() {
+        let five = from_type(&Type::U16(5)).unwrap();
+        assert!(equal(&five, &serde_json::from_str("5.0").unwrap()));
+        assert!(equal(&five, &Value::from(5i64)));
+        assert!(!equal(&five, &Value::from("5")));
+        assert_eq!(compare(&five, &Value::from(4.5)), Some(Ordering::Greater));
+        assert_eq!(compare(&five, &Value::from(u64::MAX)), Some(Ordering::Less));
+        assert_eq!(compare(&five, &Value::from("6")), None);
+    }
+}
@@ -1,4 +1,4 @@
-use super::Field;
+use super::{CheckOperand, Constraints, Field, Filter, ReferentialAction, Type, dbtype};
 use byteorder::{LittleEndian, ReadBytesExt};
 use std::io::Cursor;
 
@@ -6,32 +6,161 @@ use std::io::Cursor;
 pub struct TableMetadata {
     fields: Vec<Field>,
     record_size: u16,
-    primary_key_index: u16,
+    /// Fields whose values together identify a record
+    primary_key: Vec<u16>,
     indexes: Vec<u16>,
+    constraints: Constraints,
 }
 
 impl TableMetadata {
     pub fn new(
         fields: Vec<Field>,
-        primary_key_index: u16,
+        primary_key: Vec<u16>,
         indexes: Vec<u16>,
+        constraints: Constraints,
     ) -> Result<Self, &'static str> {
-        if primary_key_index as usize >= fields.len() {
-            return Err("Wrong primary key location");
-        } else if fields[primary_key_index as usize].nullable {
-            return Err("Primary key cannot be nullable");
+        if primary_key.is_empty() {
+            return Err("Primary key must contain at least one field");
+        }
+        for (i, &key) in primary_key.iter().enumerate() {
+            if key as usize >= fields.len() {
+                return Err("Wrong primary key location");
+            } else if fields[key as usize].nullable {
+                return Err("Primary key cannot be nullable");
+            } else if primary_key[..i].contains(&key) {
+                return Err("Primary key contains a field more than once");
+            }
+        }
+        for field in &fields {
+            let Some(expression) = &field.generated else {
+                continue;
+            };
+            if field.auto_increment || field.generate_uuid.is_some() || field.default.is_some() {
+                return Err("Generated fields cannot have defaults or be auto incremented");
+            }
+            for used in expression.fields() {
+                match fields.get(used as usize) {
+                    None => return Err("Wrong location of a field used by a generated field"),
+                    Some(used) if used.generated.is_some() => {
+                        return Err("Generated fields cannot use other generated fields");
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        // Primary key never changes, while generated values do
+        if primary_key
+            .iter()
+            .any(|&key| fields[key as usize].generated.is_some())
+        {
+            return Err("Generated fields cannot be part of the primary key");
         }
         for &index in &indexes {
             if index as usize >= fields.len() {
                 return Err("Wrong primary key location");
             }
         }
-        let record_size = fields.iter().map(|f| f.type_.size() as u16).sum();
+        // Long texts, blobs and jsons live in overflow pages, so they cannot be used as keys
+        for &index in indexes.iter().chain(&primary_key) {
+            if matches!(
+                fields[index as usize].type_,
+                Type::Text(_) | Type::Blob(_) | Type::Json(_)
+            ) {
+                return Err("Text, blob and json fields cannot be indexed");
+            }
+        }
+        for constraint in &constraints.unique {
+            if constraint.fields.is_empty() {
+                return Err("Unique constraint must contain at least one field");
+            }
+            for (i, &field) in constraint.fields.iter().enumerate() {
+                if field as usize >= fields.len() {
+                    return Err("Wrong unique constraint field location");
+                } else if constraint.fields[..i].contains(&field) {
+                    return Err("Unique constraint contains a field more than once");
+                } else if matches!(
+                    fields[field as usize].type_,
+                    Type::Text(_) | Type::Blob(_) | Type::Json(_)
+                ) {
+                    return Err("Text, blob and json fields cannot be unique");
+                }
+            }
+        }
+        for constraint in &constraints.foreign_keys {
+            if constraint.fields.is_empty() {
+                return Err("Foreign key must contain at least one field");
+            }
+            for (i, &field) in constraint.fields.iter().enumerate() {
+                if field as usize >= fields.len() {
+                    return Err("Wrong foreign key field location");
+                } else if constraint.fields[..i].contains(&field) {
+                    return Err("Foreign key contains a field more than once");
+                } else if constraint.on_delete == ReferentialAction::SetNull
+                    && !fields[field as usize].nullable
+                {
+                    return Err("Fields of a foreign key that sets null must be nullable");
+                }
+            }
+            // The referenced table is found next to the table by its name
+            if !is_file_name(&constraint.table) {
+                return Err(
+                    "Referenced table name must be from 1 to 255 bytes without slashes, other than '.' and '..'",
+                );
+            }
+        }
+        for constraint in &constraints.checks {
+            let Some(field) = fields.get(constraint.field as usize) else {
+                return Err("Wrong checked field location");
+            };
+            if matches!(field.type_, Type::Text(_) | Type::Blob(_) | Type::Json(_)) {
+                return Err("Text, blob and json fields cannot be checked");
+            }
+            match constraint.filter {
+                Filter::IsNull | Filter::IsNotNull => continue,
+                Filter::HasKey => return Err("Key existence cannot be checked"),
+                Filter::In | Filter::NotIn | Filter::Between => {
+                    return Err("Filters with a list of values cannot be checked");
+                }
+                Filter::Contains | Filter::StartsWith | Filter::EndsWith
+                    if !matches!(field.type_, Type::Varchar(_, _)) =>
+                {
+                    return Err("String filters can be checked only on string fields");
+                }
+                _ => {}
+            }
+            let operand_type = match &constraint.operand {
+                CheckOperand::Value(value) => value,
+                CheckOperand::Field(other) => match fields.get(*other as usize) {
+                    Some(other) => &other.type_,
+                    None => return Err("Wrong compared field location"),
+                },
+            };
+            if operand_type.serialize_definition() != field.type_.serialize_definition() {
+                return Err("Checked field is compared with a value of another type");
+            }
+        }
+        let names: Vec<&str> = constraints.names().collect();
+        for (i, name) in names.iter().enumerate() {
+            // Names are used as file names
+            if !is_file_name(name) {
+                return Err(
+                    "Constraint name must be from 1 to 255 bytes without slashes, other than '.' and '..'",
+                );
+            } else if *name == "primary_key" {
+                // It's the name of the primary key in constraint violations
+                return Err("Constraint name 'primary_key' is reserved");
+            } else if names[..i].contains(name) {
+                return Err("Constraint names must be unique");
+            }
+        }
+        let record_structure: Vec<Type> = fields.iter().map(|f| f.type_.clone()).collect();
+        let record_size = dbtype::record_size(&record_structure);
         Ok(TableMetadata {
             fields,
             record_size,
-            primary_key_index,
+            primary_key,
             indexes,
+            constraints,
         })
     }
 
@@ -52,9 +181,17 @@ impl TableMetadata {
             .read_u16::<LittleEndian>()
             .map_err(|_| "Corrupted data: missing record_size")?;
 
-        let primary_key_index = rdr
-            .read_u16::<LittleEndian>()
-            .map_err(|_| "Corrupted data: missing primary_key_index")?;
+        let primary_key_count =
+            rdr.read_u16::<LittleEndian>()
+                .map_err(|_| "Corrupted data: missing primary key length")? as usize;
+
+        let mut primary_key = Vec::with_capacity(primary_key_count);
+        for _ in 0..primary_key_count {
+            let idx = rdr
+                .read_u16::<LittleEndian>()
+                .map_err(|_| "Corrupted data: incomplete primary key")?;
+            primary_key.push(idx);
+        }
 
         let index_count = rdr
             .read_u16::<LittleEndian>()
@@ -68,11 +205,14 @@ impl TableMetadata {
             indexes.push(idx);
         }
 
+        let constraints = Constraints::deserialize(&mut rdr)?;
+
         Ok(TableMetadata {
             fields,
             record_size,
-            primary_key_index,
+            primary_key,
             indexes,
+            constraints,
         })
     }
 
@@ -83,11 +223,15 @@ impl TableMetadata {
             descriptor_content.extend_from_slice(&field.serialize());
         }
         descriptor_content.extend_from_slice(&self.record_size.to_le_bytes());
-        descriptor_content.extend_from_slice(&self.primary_key_index.to_le_bytes());
+        descriptor_content.extend_from_slice(&(self.primary_key.len() as u16).to_le_bytes());
+        for key in &self.primary_key {
+            descriptor_content.extend_from_slice(&key.to_le_bytes());
+        }
         descriptor_content.extend_from_slice(&(self.indexes.len() as u16).to_le_bytes());
         for index in &self.indexes {
             descriptor_content.extend_from_slice(&index.to_le_bytes());
         }
+        descriptor_content.extend_from_slice(&self.constraints.serialize());
         Ok(descriptor_content)
     }
 
@@ -99,8 +243,24 @@ impl TableMetadata {
         &self.fields
     }
 
-    pub fn primary_key(&self) -> u16 {
-        self.primary_key_index
+    /// Types of the fields in the order they are stored in a record
+    pub fn record_structure(&self) -> Vec<Type> {
+        self.fields
+            .iter()
+            .map(|field| field.type_.clone())
+            .collect()
+    }
+
+    pub fn primary_key(&self) -> &[u16] {
+        &self.primary_key
+    }
+
+    /// Values of the primary key fields of the record
+    pub fn primary_key_values(&self, record: &[Type]) -> Vec<Type> {
+        self.primary_key
+            .iter()
+            .map(|&idx| record[idx as usize].clone())
+            .collect()
     }
 
     pub fn indexes(&self) -> Vec<&Field> {
@@ -113,4 +273,204 @@ impl TableMetadata {
     pub fn indexes_idx(&self) -> &[u16] {
         &self.indexes
     }
+
+    pub fn constraints(&self) -> &Constraints {
+        &self.constraints
+    }
+}
+
+/// Whether the name can be used as the name of a file in the directory of a table or
+/// the database
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= u8::MAX as usize
+        && !name.contains(['/', '\\'])
+        && name != "."
+        && name != ".."
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{CheckConstraint, Collation, Expression, ForeignKey, UniqueConstraint};
+    use super::*;
+
+    fn field(name: &str, type_: Type) -> Field {
+        Field {
+            name: String::from(name),
+            type_,
+            nullable: false,
+            truncate: false,
+            auto_increment: false,
+            generate_uuid: None,
+            default: None,
+            generated: None,
+            collation: Collation::Binary,
+        }
+    }
+
+    #[test]
+    fn composite_primary_key() {
+        let fields = vec![
+            field("tenant_id", Type::U32(0)),
+            field("order_id", Type::U64(0)),
+            field("comment", Type::Text(String::new())),
+        ];
+        let constraints = Constraints {
+            unique: vec![UniqueConstraint {
+                name: String::from("unique_order"),
+                fields: vec![1],
+            }],
+            ..Default::default()
+        };
+        let metadata =
+            TableMetadata::new(fields.clone(), vec![0, 1], vec![1], constraints).unwrap();
+        let serialized = metadata.serialize().unwrap();
+        assert_eq!(
+            TableMetadata::deserialize(&serialized),
+            Ok(metadata.clone())
+        );
+        assert_eq!(
+            metadata.primary_key_values(&[
+                Type::U32(7),
+                Type::U64(42),
+                Type::Text(String::from("gift"))
+            ]),
+            vec![Type::U32(7), Type::U64(42)]
+        );
+
+        let no_constraints = Constraints::default;
+        assert!(TableMetadata::new(fields.clone(), vec![], vec![], no_constraints()).is_err());
+        assert!(TableMetadata::new(fields.clone(), vec![0, 0], vec![], no_constraints()).is_err());
+        assert!(TableMetadata::new(fields.clone(), vec![0, 2], vec![], no_constraints()).is_err());
+
+        let unique = |name: &str, fields: Vec<u16>| UniqueConstraint {
+            name: String::from(name),
+            fields,
+        };
+        for unique in [
+            vec![unique("unique_comment", vec![2])],
+            vec![unique("", vec![0])],
+            vec![unique("a/b", vec![0])],
+            vec![unique(".", vec![0])],
+            vec![unique("..", vec![0])],
+            vec![unique("primary_key", vec![0])],
+            vec![unique("same", vec![0]), unique("same", vec![1])],
+        ] {
+            let constraints = Constraints {
+                unique,
+                ..Default::default()
+            };
+            assert!(TableMetadata::new(fields.clone(), vec![0], vec![], constraints).is_err());
+        }
+    }
+
+    #[test]
+    fn foreign_keys() {
+        let mut customer_id = field("customer_id", Type::U64(0));
+        customer_id.nullable = true;
+        let fields = vec![field("id", Type::U64(0)), customer_id];
+        let foreign_key = |fields: Vec<u16>, table: &str, on_delete| ForeignKey {
+            name: String::from("fk_customer_id"),
+            fields,
+            table: String::from(table),
+            on_delete,
+        };
+        let with_foreign_key = |foreign_key| Constraints {
+            foreign_keys: vec![foreign_key],
+            ..Default::default()
+        };
+
+        let constraints = with_foreign_key(foreign_key(
+            vec![1],
+            "customers",
+            ReferentialAction::SetNull,
+        ));
+        let metadata = TableMetadata::new(fields.clone(), vec![0], vec![], constraints).unwrap();
+        let serialized = metadata.serialize().unwrap();
+        assert_eq!(TableMetadata::deserialize(&serialized), Ok(metadata));
+
+        for foreign_key in [
+            foreign_key(vec![], "customers", ReferentialAction::Restrict),
+            foreign_key(vec![2], "customers", ReferentialAction::Restrict),
+            foreign_key(vec![1, 1], "customers", ReferentialAction::Cascade),
+            foreign_key(vec![0], "customers", ReferentialAction::SetNull),
+            foreign_key(vec![1], "", ReferentialAction::Restrict),
+            foreign_key(vec![1], "../customers", ReferentialAction::Restrict),
+            foreign_key(vec![1], ".", ReferentialAction::Restrict),
+            foreign_key(vec![1], "..", ReferentialAction::Restrict),
+        ] {
+            let constraints = with_foreign_key(foreign_key);
+            assert!(TableMetadata::new(fields.clone(), vec![0], vec![], constraints).is_err());
+        }
+    }
+
+    #[test]
+    fn checks() {
+        let fields = vec![
+            field("id", Type::U64(0)),
+            field("quantity", Type::I32(0)),
+            field("code", Type::Varchar(8, String::new())),
+            field("note", Type::Text(String::new())),
+        ];
+        let check = |field, filter, operand| Constraints {
+            checks: vec![CheckConstraint {
+                name: String::from("check"),
+                field,
+                filter,
+                operand,
+            }],
+            ..Default::default()
+        };
+        let valid = check(1, Filter::Greater, CheckOperand::Value(Type::I32(0)));
+        let metadata = TableMetadata::new(fields.clone(), vec![0], vec![], valid).unwrap();
+        let serialized = metadata.serialize().unwrap();
+        assert_eq!(TableMetadata::deserialize(&serialized), Ok(metadata));
+
+        for constraints in [
+            check(4, Filter::IsNull, CheckOperand::Value(Type::Null)),
+            check(3, Filter::IsNull, CheckOperand::Value(Type::Null)),
+            check(1, Filter::HasKey, CheckOperand::Value(Type::Null)),
+            check(1, Filter::Contains, CheckOperand::Value(Type::I32(0))),
+            check(1, Filter::Greater, CheckOperand::Value(Type::I64(0))),
+            check(1, Filter::Greater, CheckOperand::Value(Type::Null)),
+            check(1, Filter::Less, CheckOperand::Field(0)),
+            check(1, Filter::Less, CheckOperand::Field(9)),
+        ] {
+            assert!(TableMetadata::new(fields.clone(), vec![0], vec![], constraints).is_err());
+        }
+    }
+
+    #[test]
+    fn generated_fields() {
+        let total = |expression| {
+            let mut total = field("total", Type::I64(0));
+            total.generated = Some(expression);
+            total
+        };
+        let used = |idx| Box::new(Expression::Field(idx));
+        let price_by_qty = Expression::Multiply(used(1), used(2));
+        let fields = |total| {
+            vec![
+                field("id", Type::U64(0)),
+                field("price", Type::I32(0)),
+                field("qty", Type::I32(0)),
+                total,
+            ]
+        };
+        let no_constraints = Constraints::default;
+        let valid = fields(total(price_by_qty.clone()));
+        assert!(TableMetadata::new(valid, vec![0], vec![3], no_constraints()).is_ok());
+
+        let mut with_default = total(price_by_qty.clone());
+        with_default.default = Some(Type::I64(0));
+        for fields in [
+            fields(total(Expression::Add(used(1), used(4)))),
+            fields(total(Expression::Add(used(1), used(3)))),
+            fields(with_default),
+        ] {
+            assert!(TableMetadata::new(fields, vec![0], vec![], no_constraints()).is_err());
+        }
+        let generated_key = fields(total(price_by_qty));
+        assert!(TableMetadata::new(generated_key, vec![3], vec![], no_constraints()).is_err());
+    }
 }
@@ -5,8 +5,8 @@ use super::database;
 use std::path::PathBuf;
 use table_api::table_service_server::TableService;
 use table_api::{
-    AddRequest, ChangeRequest, DeleteRequest, RecordsInfo, SelectRequest, Table, TableList,
-    TableMetadata, Void,
+    AddRequest, AddResponse, ChangeRequest, DeleteRequest, RecordsInfo, SelectRequest, Table,
+    TableList, TableMetadata, Void,
 };
 use tonic::{Request, Response, Status};
 
@@ -43,6 +43,53 @@ impl MyTableService {
     }
 }
 
+impl From<database::Error> for Status {
+    fn from(error: database::Error) -> Self {
+        match error {
+            database::Error::ConstraintViolation(_, _) => {
+                Status::new(tonic::Code::FailedPrecondition, error.to_string())
+            }
+            database::Error::Other(details) => Status::new(tonic::Code::Aborted, details),
+        }
+    }
+}
+
+/// Name of a constraint made of its kind and names of its fields, e.g. 'unique_email'
+fn default_constraint_name(kind: &str, fields: &[structures::Field], constraint: &[u16]) -> String {
+    let mut name = String::from(kind);
+    for &idx in constraint {
+        name.push('_');
+        match fields.get(idx as usize) {
+            Some(field) => name.push_str(&field.name),
+            None => name.push_str(&idx.to_string()),
+        }
+    }
+    name
+}
+
+/// Filter of a select request, all records are selected if it's not specified
+fn filter_from_proto(
+    filter: Option<table_api::FilterExpression>,
+) -> Result<structures::FilterExpression, String> {
+    match filter {
+        Some(filter) => filter.try_into(),
+        None => Ok(structures::FilterExpression::all()),
+    }
+}
+
+/// Filter of a request that deletes or changes records. Old clients send their filters in
+/// a field that is ignored now, so a missing filter must not affect all records
+fn required_filter_from_proto(
+    filter: Option<table_api::FilterExpression>,
+) -> Result<structures::FilterExpression, String> {
+    match filter {
+        Some(filter) => filter.try_into(),
+        None => Err(String::from(
+            "Filter is not specified, an empty 'and' expression matches all records.",
+        )),
+    }
+}
+
 #[tonic::async_trait]
 impl TableService for MyTableService {
     async fn get_table_list(&self, _: Request<Void>) -> Result<Response<TableList>, Status> {
@@ -68,21 +115,77 @@ impl TableService for MyTableService {
     ) -> Result<Response<Void>, Status> {
         let table_info = request.into_inner();
         let table_name = table_info.name;
-        let fields = table_info
+        let fields: Vec<structures::Field> = match table_info
             .fields
             .into_iter()
-            .map(|proto_field| {
-                let type_ = proto_field.r#type().into();
-                structures::Field {
-                    name: proto_field.name,
-                    type_,
-                    nullable: false,
+            .map(|proto_field| proto_field.try_into())
+            .collect()
+        {
+            Ok(fields) => fields,
+            Err(error_details) => {
+                return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+            }
+        };
+        let pk = table_info
+            .primary_key
+            .into_iter()
+            .map(|el| el as u16)
+            .collect();
+        let indexes = table_info.indexes.into_iter().map(|el| el as u16).collect();
+        let unique = table_info
+            .unique
+            .into_iter()
+            .map(|constraint| {
+                let mut constraint: structures::UniqueConstraint = constraint.into();
+                if constraint.name.is_empty() {
+                    constraint.name =
+                        default_constraint_name("unique", &fields, &constraint.fields);
                 }
+                constraint
             })
             .collect();
-        let pk = table_info.primary_key as u16;
-        let indexes = table_info.indexes.into_iter().map(|el| el as u16).collect();
-        let metadata = match structures::TableMetadata::new(fields, pk, indexes) {
+        let foreign_keys = table_info
+            .foreign_keys
+            .into_iter()
+            .map(|constraint| {
+                let mut constraint: structures::ForeignKey = constraint.into();
+                if constraint.name.is_empty() {
+                    constraint.name = default_constraint_name("fk", &fields, &constraint.fields);
+                }
+                constraint
+            })
+            .collect();
+        let checks = match table_info
+            .checks
+            .into_iter()
+            .map(|constraint| {
+                let mut constraint: structures::CheckConstraint = constraint.try_into()?;
+                if constraint.name.is_empty() {
+                    constraint.name =
+                        default_constraint_name("check", &fields, &[constraint.field]);
+                }
+                // Constants take the form of the checked field
+                if let structures::CheckOperand::Value(value) = &mut constraint.operand
+                    && *value != structures::Type::Null
+                    && let Some(field) = fields.get(constraint.field as usize)
+                {
+                    *value = field.prepare_value(value.clone())?;
+                }
+                Ok(constraint)
+            })
+            .collect::<Result<Vec<_>, String>>()
+        {
+            Ok(checks) => checks,
+            Err(error_details) => {
+                return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+            }
+        };
+        let constraints = structures::Constraints {
+            unique,
+            foreign_keys,
+            checks,
+        };
+        let metadata = match structures::TableMetadata::new(fields, pk, indexes, constraints) {
             Ok(metadata) => metadata,
             Err(error_details) => {
                 return Err(Status::new(tonic::Code::Aborted, error_details));
@@ -99,7 +202,7 @@ impl TableService for MyTableService {
         let table_path = self.database_path.join(table.name);
         match database::clear_table(&table_path) {
             Ok(()) => Ok(Response::new(Void {})),
-            Err(error_details) => Err(Status::new(tonic::Code::Aborted, error_details)),
+            Err(error) => Err(error.into()),
         }
     }
 
@@ -108,7 +211,7 @@ impl TableService for MyTableService {
         let table_path = self.database_path.join(table.name);
         match database::delete_table(&table_path) {
             Ok(()) => Ok(Response::new(Void {})),
-            Err(err_info) => Err(Status::new(tonic::Code::Aborted, err_info)),
+            Err(error) => Err(error.into()),
         }
     }
 
@@ -127,26 +230,38 @@ impl TableService for MyTableService {
         let request = request.into_inner();
         let table_name = request.table.unwrap().name;
         let table_path = self.database_path.join(table_name);
-        let filters: Vec<database::structures::FilterOption> = request
-            .filters
-            .into_iter()
-            .map(|filter_option| filter_option.try_into().unwrap())
-            .collect();
+        let filter = match filter_from_proto(request.filter) {
+            Ok(filter) => filter,
+            Err(error_details) => {
+                return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+            }
+        };
 
-        match database::get_records(&table_path, &filters) {
+        match database::get_records(&table_path, &filter) {
             Ok(data) => Ok(Response::new(data.into())),
             Err(error_details) => Err(Status::new(tonic::Code::Aborted, error_details)),
         }
     }
 
-    async fn add_records(&self, request: Request<AddRequest>) -> Result<Response<Void>, Status> {
+    async fn add_records(
+        &self,
+        request: Request<AddRequest>,
+    ) -> Result<Response<AddResponse>, Status> {
         let request = request.into_inner();
         let table_name = request.table.unwrap().name;
         let table_path = self.database_path.join(table_name);
-        let records: Vec<Vec<database::structures::Type>> = request.records.unwrap().into();
-        match add_records(&table_path, records) {
-            Ok(_) => Ok(Response::new(Void {})),
-            Err(error_details) => Err(Status::new(tonic::Code::Aborted, error_details)),
+        let records: Vec<Vec<Option<database::structures::Type>>> =
+            match request.records.unwrap().try_into() {
+                Ok(records) => records,
+                Err(error_details) => {
+                    return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+                }
+            };
+        match add_records(&table_path, &request.columns, records) {
+            Ok(keys) => Ok(Response::new(AddResponse {
+                keys: Some(keys.into()),
+            })),
+            Err(error) => Err(error.into()),
         }
     }
 
@@ -157,15 +272,16 @@ impl TableService for MyTableService {
         let request = request.into_inner();
         let table_name = request.table.unwrap().name;
         let table_path = self.database_path.join(table_name);
-        let filters: Vec<database::structures::FilterOption> = request
-            .filters
-            .into_iter()
-            .map(|filter_option| filter_option.try_into().unwrap())
-            .collect();
+        let filter = match required_filter_from_proto(request.filter) {
+            Ok(filter) => filter,
+            Err(error_details) => {
+                return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+            }
+        };
 
-        match database::delete_records(&table_path, &filters) {
+        match database::delete_records(&table_path, &filter) {
             Ok(_) => Ok(Response::new(Void {})),
-            Err(error_details) => Err(Status::new(tonic::Code::Aborted, error_details)),
+            Err(error) => Err(error.into()),
         }
     }
 
@@ -178,18 +294,24 @@ impl TableService for MyTableService {
         let table_name = request.table.unwrap().name;
         let table_path = self.database_path.join(table_name);
 
-        let filters: Vec<database::structures::FilterOption> = request
-            .filters
-            .into_iter()
-            .map(|filter_option| filter_option.try_into().unwrap())
-            .collect();
+        let filter = match required_filter_from_proto(request.filter) {
+            Ok(filter) => filter,
+            Err(error_details) => {
+                return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+            }
+        };
 
-        let structure = request
+        let structure = match request
             .pattern
             .into_iter()
-            .map(|field| field.into())
-            .collect();
-
+            .map(|field| field.try_into())
+            .collect()
+        {
+            Ok(structure) => structure,
+            Err(error_details) => {
+                return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+            }
+        };
         let values = match request.new_values {
             Some(value) => value,
             None => {
@@ -199,11 +321,147 @@ impl TableService for MyTableService {
                 ));
             }
         }
-        .into();
+        .try_into();
+        let values = match values {
+            Ok(values) => values,
+            Err(error_details) => {
+                return Err(Status::new(tonic::Code::InvalidArgument, error_details));
+            }
+        };
 
-        match change_records(&table_path, &filters, structure, values) {
+        match change_records(&table_path, &filter, structure, values) {
             Ok(_) => Ok(Response::new(Void {})),
-            Err(error_details) => Err(Status::new(tonic::Code::Aborted, error_details)),
+            Err(error) => Err(error.into()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use structures::{Collation, Constraints, Field, Type};
+
+    /// Delete request of clients built before filter expressions
+    #[derive(Clone, PartialEq, Message)]
+    struct OldDeleteRequest {
+        #[prost(message, optional, tag = "1")]
+        table: Option<Table>,
+        #[prost(message, repeated, tag = "3")]
+        filters: Vec<table_api::FilterOption>,
+    }
+
+    /// Change request of clients built before filter expressions
+    #[derive(Clone, PartialEq, Message)]
+    struct OldChangeRequest {
+        #[prost(message, optional, tag = "1")]
+        table: Option<Table>,
+        #[prost(message, repeated, tag = "2")]
+        pattern: Vec<table_api::Field>,
+        #[prost(message, optional, tag = "3")]
+        new_values: Option<table_api::ValueSequence>,
+        #[prost(message, repeated, tag = "4")]
+        filters: Vec<table_api::FilterOption>,
+    }
+
+    #[tokio::test]
+    async fn requests_of_old_clients_are_rejected() {
+        let database_path =
+            std::env::temp_dir().join(format!("db-engine-old-clients-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&database_path);
+        let field = |name: &str| Field {
+            name: String::from(name),
+            type_: Type::I32(0),
+            nullable: false,
+            truncate: false,
+            auto_increment: false,
+            generate_uuid: None,
+            default: None,
+            generated: None,
+            collation: Collation::Binary,
+        };
+        let metadata = structures::TableMetadata::new(
+            vec![field("id"), field("count")],
+            vec![0],
+            vec![],
+            Constraints::default(),
+        );
+        database::create_table(&database_path, "items", metadata.unwrap()).unwrap();
+        let records = (0..3)
+            .map(|id| vec![Some(Type::I32(id)), Some(Type::I32(0))])
+            .collect();
+        add_records(&database_path.join("items"), &[], records).unwrap();
+        let service = MyTableService::new(database_path.clone()).unwrap();
+        let table = || {
+            Some(Table {
+                name: String::from("items"),
+            })
+        };
+        let filters = vec![table_api::FilterOption {
+            column: String::from("id"),
+            filter: table_api::Filter::Equal.into(),
+            value: Some(Type::I32(1).into()),
+            ..Default::default()
+        }];
+
+        let old_delete = OldDeleteRequest {
+            table: table(),
+            filters: filters.clone(),
+        };
+        let request = DeleteRequest::decode(old_delete.encode_to_vec().as_slice()).unwrap();
+        let status = service
+            .delete_records(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().starts_with("Filter is not specified"));
+
+        let old_change = OldChangeRequest {
+            table: table(),
+            pattern: vec![table_api::Field {
+                name: String::from("count"),
+                ..Default::default()
+            }],
+            new_values: Some(table_api::ValueSequence {
+                sequence: vec![Type::I32(5).into()],
+            }),
+            filters,
+        };
+        let request = ChangeRequest::decode(old_change.encode_to_vec().as_slice()).unwrap();
+        let status = service
+            .change_records(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().starts_with("Filter is not specified"));
+
+        let select = |filter| SelectRequest {
+            table: table(),
+            filter,
+        };
+        let response = service.select_records(Request::new(select(None))).await;
+        let records: Vec<Vec<Option<Type>>> = response.unwrap().into_inner().try_into().unwrap();
+        assert_eq!(
+            records,
+            (0..3)
+                .map(|id| vec![Some(Type::I32(id)), Some(Type::I32(0))])
+                .collect::<Vec<_>>()
+        );
+
+        // Deleting all records takes an explicit expression
+        let all = table_api::FilterExpression {
+            kind: Some(table_api::filter_expression::Kind::And(
+                table_api::FilterExpressions::default(),
+            )),
+        };
+        let request = DeleteRequest {
+            table: table(),
+            filter: Some(all),
+        };
+        service.delete_records(Request::new(request)).await.unwrap();
+        let response = service.select_records(Request::new(select(None))).await;
+        let records: Vec<Vec<Option<Type>>> = response.unwrap().into_inner().try_into().unwrap();
+        assert!(records.is_empty());
+        std::fs::remove_dir_all(&database_path).unwrap();
+    }
+}
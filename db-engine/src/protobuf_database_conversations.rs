@@ -1,5 +1,6 @@
 use crate::database::structures as db;
 use crate::grpc_server::table_api as proto;
+use db::dbtype::MAX_DECIMAL_PRECISION;
 use proto::value::Kind as val;
 
 impl From<proto::Type> for db::Type {
@@ -14,9 +15,23 @@ impl From<proto::Type> for db::Type {
             proto::Type::U16 => db::Type::U16(0),
             proto::Type::U32 => db::Type::U32(0),
             proto::Type::U64 => db::Type::U64(0),
-            proto::Type::F32 => unimplemented!(),
-            proto::Type::F64 => unimplemented!(),
-            proto::Type::String => unimplemented!(),
+            proto::Type::I128 => db::Type::I128(0),
+            proto::Type::U128 => db::Type::U128(0),
+            proto::Type::Date => db::Type::Date(0),
+            proto::Type::Time => db::Type::Time(0),
+            proto::Type::Timestamp => db::Type::Timestamp(0),
+            proto::Type::TimestampTz => db::Type::TimestampTz(0, 0),
+            proto::Type::Decimal => db::Type::Decimal(MAX_DECIMAL_PRECISION, 0, 0),
+            proto::Type::Text => db::Type::Text(String::new()),
+            proto::Type::Blob => db::Type::Blob(Vec::new()),
+            proto::Type::Binary => db::Type::Binary(u8::MAX, Vec::new()),
+            proto::Type::Bytes => db::Type::Bytes(u8::MAX, Vec::new()),
+            proto::Type::Uuid => db::Type::Uuid(0),
+            proto::Type::Enum => db::Type::Enum(Vec::new().into(), 0),
+            proto::Type::Json => db::Type::Json(Vec::new()),
+            proto::Type::F32 => db::Type::F32(0.0),
+            proto::Type::F64 => db::Type::F64(0.0),
+            proto::Type::String => db::Type::Varchar(u8::MAX, String::new()),
         }
     }
 }
@@ -40,8 +55,35 @@ impl TryFrom<proto::Value> for db::Type {
             val::U16(data) => db::Type::U16(data as u16),
             val::U32(data) => db::Type::U32(data),
             val::U64(data) => db::Type::U64(data),
-            val::F32(_) => unimplemented!(),
-            val::F64(_) => unimplemented!(),
+            // Actual length is defined by the field the value is stored in
+            val::String(data) => db::Type::Varchar(data.len().min(u8::MAX as usize) as u8, data),
+            val::F32(data) => db::Type::F32(data),
+            val::F64(data) => db::Type::F64(data),
+            val::Null(_) => db::Type::Null,
+            val::I128(data) => db::Type::I128(data.into()),
+            val::U128(data) => db::Type::U128(data.into()),
+            val::Date(data) => db::Type::Date(data),
+            val::Time(data) => db::Type::Time(data),
+            val::Timestamp(data) => db::Type::Timestamp(data),
+            val::TimestampTz(data) => {
+                let offset = i16::try_from(data.offset_minutes).map_err(|_| {
+                    format!("Time zone offset {} is out of range.", data.offset_minutes)
+                })?;
+                db::Type::TimestampTz(data.micros, offset)
+            }
+            val::Decimal(data) => {
+                let scale = match u8::try_from(data.scale) {
+                    Ok(scale) if scale <= MAX_DECIMAL_PRECISION => scale,
+                    _ => return Err(format!("Decimal scale {} is out of range.", data.scale)),
+                };
+                let unscaled = data.unscaled.unwrap_or_default().into();
+                db::Type::Decimal(MAX_DECIMAL_PRECISION, scale, unscaled)
+            }
+            val::Bytes(data) => db::Type::Blob(data),
+            val::Uuid(data) => match uuid::Uuid::parse_str(&data) {
+                Ok(uuid) => db::Type::Uuid(uuid.as_u128()),
+                Err(_) => return Err(format!("'{}' is not a valid UUID.", data)),
+            },
         })
     }
 }
@@ -58,21 +100,233 @@ impl From<db::Type> for proto::Value {
             db::Type::U16(data) => val::U16(data as u32),
             db::Type::U32(data) => val::U32(data),
             db::Type::U64(data) => val::U64(data),
-            _ => unimplemented!(),
+            db::Type::F32(data) => val::F32(data),
+            db::Type::F64(data) => val::F64(data),
+            db::Type::Varchar(_, data) => val::String(data),
+            db::Type::Null => val::Null(proto::Null {}),
+            db::Type::I128(data) => val::I128(data.into()),
+            db::Type::U128(data) => val::U128(data.into()),
+            db::Type::Date(data) => val::Date(data),
+            db::Type::Time(data) => val::Time(data),
+            db::Type::Timestamp(data) => val::Timestamp(data),
+            db::Type::TimestampTz(micros, offset) => val::TimestampTz(proto::TimestampTz {
+                micros,
+                offset_minutes: offset as i32,
+            }),
+            db::Type::Decimal(_, scale, unscaled) => val::Decimal(proto::Decimal {
+                unscaled: Some(unscaled.into()),
+                scale: scale as u32,
+            }),
+            db::Type::Text(data) => val::String(data),
+            db::Type::Blob(data) => val::Bytes(data),
+            db::Type::Binary(_, data) => val::Bytes(data),
+            db::Type::Bytes(_, data) => val::Bytes(data),
+            db::Type::Uuid(data) => val::Uuid(uuid::Uuid::from_u128(data).to_string()),
+            db::Type::Json(data) => val::String(
+                db::json::to_string(&data).expect("Json documents are validated on insert"),
+            ),
+            db::Type::Enum(labels, ordinal) => {
+                val::String(labels.get(ordinal as usize).cloned().unwrap_or_default())
+            }
+            db::Type::Overflow(_, _) => {
+                unreachable!("Overflow values are loaded before leaving the database")
+            }
         };
         Self { kind: Some(kind) }
     }
 }
 
-impl From<proto::Field> for db::Field {
-    fn from(value: proto::Field) -> Self {
-        let type_ = value.r#type().into();
-        let name = value.name;
-        db::Field {
-            name,
+impl From<proto::Int128> for i128 {
+    fn from(value: proto::Int128) -> Self {
+        ((value.high as i128) << 64) | value.low as i128
+    }
+}
+
+impl From<i128> for proto::Int128 {
+    fn from(value: i128) -> Self {
+        proto::Int128 {
+            high: (value >> 64) as i64,
+            low: value as u64,
+        }
+    }
+}
+
+impl From<proto::UInt128> for u128 {
+    fn from(value: proto::UInt128) -> Self {
+        ((value.high as u128) << 64) | value.low as u128
+    }
+}
+
+impl From<u128> for proto::UInt128 {
+    fn from(value: u128) -> Self {
+        proto::UInt128 {
+            high: (value >> 64) as u64,
+            low: value as u64,
+        }
+    }
+}
+
+impl TryFrom<proto::Field> for db::Field {
+    type Error = String;
+    fn try_from(value: proto::Field) -> Result<Self, Self::Error> {
+        let max_length = match value.max_length {
+            0 => u8::MAX,
+            len => u8::try_from(len).map_err(|_| {
+                format!(
+                    "Maximum length of '{}' field is {}, but it cannot exceed {}.",
+                    value.name,
+                    len,
+                    u8::MAX
+                )
+            })?,
+        };
+        let type_ = match value.r#type() {
+            proto::Type::String => db::Type::Varchar(max_length, String::new()),
+            proto::Type::Bytes => db::Type::Bytes(max_length, Vec::new()),
+            proto::Type::Binary => {
+                if value.max_length == 0 {
+                    return Err(format!(
+                        "Length of '{}' binary field is not specified.",
+                        value.name
+                    ));
+                }
+                db::Type::Binary(max_length, Vec::new())
+            }
+            proto::Type::Decimal => {
+                let precision = match u8::try_from(value.precision) {
+                    Ok(precision) if (1..=MAX_DECIMAL_PRECISION).contains(&precision) => precision,
+                    _ => {
+                        return Err(format!(
+                            "Precision of '{}' field must be between 1 and {}.",
+                            value.name, MAX_DECIMAL_PRECISION
+                        ));
+                    }
+                };
+                if value.scale > precision as u32 {
+                    return Err(format!(
+                        "Scale of '{}' field cannot exceed its precision.",
+                        value.name
+                    ));
+                }
+                db::Type::Decimal(precision, value.scale as u8, 0)
+            }
+            proto::Type::Enum => {
+                if value.labels.is_empty() || value.labels.len() > u8::MAX as usize {
+                    return Err(format!(
+                        "Enum '{}' field must have between 1 and {} labels.",
+                        value.name,
+                        u8::MAX
+                    ));
+                }
+                for (i, label) in value.labels.iter().enumerate() {
+                    if label.len() > u8::MAX as usize {
+                        return Err(format!(
+                            "Label '{}' of '{}' field takes more than {} bytes.",
+                            label,
+                            value.name,
+                            u8::MAX
+                        ));
+                    }
+                    if value.labels[..i].contains(label) {
+                        return Err(format!(
+                            "Label '{}' of '{}' field is listed more than once.",
+                            label, value.name
+                        ));
+                    }
+                }
+                db::Type::Enum(value.labels.clone().into(), 0)
+            }
+            type_ => type_.into(),
+        };
+        let generate_uuid = match value.generate_uuid() {
+            proto::UuidVersion::None => None,
+            _ if !matches!(type_, db::Type::Uuid(_)) => {
+                return Err(format!(
+                    "UUIDs cannot be generated for '{}' field that is not a uuid.",
+                    value.name
+                ));
+            }
+            proto::UuidVersion::V4 => Some(db::UuidVersion::V4),
+            proto::UuidVersion::V7 => Some(db::UuidVersion::V7),
+        };
+        if value.auto_increment && type_.integer_value().is_none() {
+            return Err(format!(
+                "'{}' field cannot be auto incremented because it is not an integer.",
+                value.name
+            ));
+        }
+        let collation = match value.collation() {
+            proto::Collation::Binary => db::Collation::Binary,
+            _ if !matches!(type_, db::Type::Varchar(_, _) | db::Type::Text(_)) => {
+                return Err(format!(
+                    "Collation cannot be set for '{}' field that is not a string.",
+                    value.name
+                ));
+            }
+            proto::Collation::CaseInsensitive => db::Collation::CaseInsensitive,
+            proto::Collation::Unicode => db::Collation::Unicode,
+        };
+        let mut field = db::Field {
+            name: value.name,
             type_,
-            nullable: false,
+            nullable: value.nullable,
+            truncate: value.truncate,
+            auto_increment: value.auto_increment,
+            generate_uuid,
+            default: None,
+            generated: None,
+            collation,
+        };
+        if let Some(default) = value.default_value {
+            field.default = Some(field.prepare_value(default.try_into()?)?);
+        }
+        if let Some(generated) = value.generated {
+            field.generated = Some(generated.try_into()?);
         }
+        Ok(field)
+    }
+}
+
+impl TryFrom<proto::Expression> for db::Expression {
+    type Error = String;
+    fn try_from(value: proto::Expression) -> Result<Self, Self::Error> {
+        use proto::expression::Kind;
+        let operands = |operands: Box<proto::Operands>| -> Result<_, String> {
+            match (operands.left, operands.right) {
+                (Some(left), Some(right)) => Ok((
+                    Box::new((*left).try_into()?),
+                    Box::new((*right).try_into()?),
+                )),
+                _ => Err(String::from("Operand of an expression is missing.")),
+            }
+        };
+        Ok(match value.kind {
+            Some(Kind::Field(field)) => db::Expression::Field(field as u16),
+            Some(Kind::Value(value)) => db::Expression::Value(value.try_into()?),
+            Some(Kind::Add(value)) => {
+                let (left, right) = operands(value)?;
+                db::Expression::Add(left, right)
+            }
+            Some(Kind::Subtract(value)) => {
+                let (left, right) = operands(value)?;
+                db::Expression::Subtract(left, right)
+            }
+            Some(Kind::Multiply(value)) => {
+                let (left, right) = operands(value)?;
+                db::Expression::Multiply(left, right)
+            }
+            Some(Kind::Divide(value)) => {
+                let (left, right) = operands(value)?;
+                db::Expression::Divide(left, right)
+            }
+            Some(Kind::Concat(value)) => {
+                let (left, right) = operands(value)?;
+                db::Expression::Concat(left, right)
+            }
+            Some(Kind::Lower(value)) => db::Expression::Lower(Box::new((*value).try_into()?)),
+            Some(Kind::Upper(value)) => db::Expression::Upper(Box::new((*value).try_into()?)),
+            None => return Err(String::from("'Expression' doesn't contain an expression.")),
+        })
     }
 }
 
@@ -90,26 +344,91 @@ impl From<Vec<Vec<db::Type>>> for proto::RecordsInfo {
     }
 }
 
-impl From<proto::ValueSequence> for Vec<db::Type> {
-    fn from(value: proto::ValueSequence) -> Self {
+impl TryFrom<proto::ValueSequence> for Vec<db::Type> {
+    type Error = String;
+    fn try_from(value: proto::ValueSequence) -> Result<Self, Self::Error> {
         value
             .sequence
             .into_iter()
-            .map(|value| value.try_into().unwrap())
+            .map(|value| value.try_into())
             .collect()
     }
 }
 
-impl From<proto::RecordsInfo> for Vec<Vec<db::Type>> {
-    fn from(value: proto::RecordsInfo) -> Self {
+/// Values without a kind are omitted, the database generates them if it can
+impl TryFrom<proto::RecordsInfo> for Vec<Vec<Option<db::Type>>> {
+    type Error = String;
+    fn try_from(value: proto::RecordsInfo) -> Result<Self, Self::Error> {
         value
             .records
             .into_iter()
-            .map(|record| record.into())
+            .map(|record| {
+                record
+                    .sequence
+                    .into_iter()
+                    .map(|value| match value.kind {
+                        Some(_) => value.try_into().map(Some),
+                        None => Ok(None),
+                    })
+                    .collect()
+            })
             .collect()
     }
 }
 
+impl From<proto::UniqueConstraint> for db::UniqueConstraint {
+    fn from(value: proto::UniqueConstraint) -> Self {
+        db::UniqueConstraint {
+            name: value.name,
+            fields: value.fields.into_iter().map(|field| field as u16).collect(),
+        }
+    }
+}
+
+impl From<proto::ReferentialAction> for db::ReferentialAction {
+    fn from(value: proto::ReferentialAction) -> Self {
+        match value {
+            proto::ReferentialAction::Restrict => db::ReferentialAction::Restrict,
+            proto::ReferentialAction::Cascade => db::ReferentialAction::Cascade,
+            proto::ReferentialAction::SetNull => db::ReferentialAction::SetNull,
+        }
+    }
+}
+
+impl From<proto::ForeignKey> for db::ForeignKey {
+    fn from(value: proto::ForeignKey) -> Self {
+        let on_delete = value.on_delete().into();
+        db::ForeignKey {
+            name: value.name,
+            fields: value.fields.into_iter().map(|field| field as u16).collect(),
+            table: value.table,
+            on_delete,
+        }
+    }
+}
+
+impl TryFrom<proto::CheckConstraint> for db::CheckConstraint {
+    type Error = String;
+    fn try_from(value: proto::CheckConstraint) -> Result<Self, Self::Error> {
+        let filter = value.filter().into();
+        let operand = match value.operand {
+            Some(proto::check_constraint::Operand::Value(value)) => {
+                db::CheckOperand::Value(value.try_into()?)
+            }
+            Some(proto::check_constraint::Operand::OtherField(field)) => {
+                db::CheckOperand::Field(field as u16)
+            }
+            None => db::CheckOperand::Value(db::Type::Null),
+        };
+        Ok(db::CheckConstraint {
+            name: value.name,
+            field: value.field as u16,
+            filter,
+            operand,
+        })
+    }
+}
+
 impl From<proto::Filter> for db::Filter {
     fn from(value: proto::Filter) -> Self {
         match value {
@@ -121,6 +440,13 @@ impl From<proto::Filter> for db::Filter {
             proto::Filter::Contains => db::Filter::Contains,
             proto::Filter::StartsWith => db::Filter::StartsWith,
             proto::Filter::EndsWith => db::Filter::EndsWith,
+            proto::Filter::IsNull => db::Filter::IsNull,
+            proto::Filter::IsNotNull => db::Filter::IsNotNull,
+            proto::Filter::HasKey => db::Filter::HasKey,
+            proto::Filter::NotEqual => db::Filter::NotEqual,
+            proto::Filter::In => db::Filter::In,
+            proto::Filter::NotIn => db::Filter::NotIn,
+            proto::Filter::Between => db::Filter::Between,
         }
     }
 }
@@ -129,13 +455,108 @@ impl TryFrom<proto::FilterOption> for db::FilterOption {
     type Error = String;
     fn try_from(value: proto::FilterOption) -> Result<Self, Self::Error> {
         let filter = value.filter().into();
-        let field = match value.column {
-            Some(value) => value,
+        if value.column.is_empty() {
+            return Err(String::from("Field is not specified."));
+        }
+        let operand = match value.value {
+            Some(operand) => operand.try_into()?,
+            None => db::Type::Null,
+        };
+        let values = value
+            .values
+            .into_iter()
+            .map(db::Type::try_from)
+            .collect::<Result<_, _>>()?;
+        db::FilterOption::new(value.column, filter, value.json_path, operand, values)
+    }
+}
+
+impl TryFrom<proto::FilterExpression> for db::FilterExpression {
+    type Error = String;
+    fn try_from(value: proto::FilterExpression) -> Result<Self, Self::Error> {
+        use proto::filter_expression::Kind;
+        let expressions = |list: proto::FilterExpressions| -> Result<Vec<_>, String> {
+            list.expressions
+                .into_iter()
+                .map(|expression| expression.try_into())
+                .collect()
+        };
+        Ok(match value.kind {
+            Some(Kind::Filter(filter)) => db::FilterExpression::Filter(filter.try_into()?),
+            Some(Kind::And(list)) => db::FilterExpression::And(expressions(list)?),
+            Some(Kind::Or(list)) => db::FilterExpression::Or(expressions(list)?),
+            Some(Kind::Not(expression)) => {
+                db::FilterExpression::Not(Box::new((*expression).try_into()?))
+            }
             None => {
-                return Err(String::from("Field is not specified."));
+                return Err(String::from(
+                    "'FilterExpression' doesn't contain an expression.",
+                ));
             }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wide_integers_round_trip() {
+        let values = [
+            db::Type::I128(i128::MIN),
+            db::Type::I128(-1),
+            db::Type::I128(i64::MAX as i128 + 1),
+            db::Type::I128(i128::MAX),
+            db::Type::U128(0),
+            db::Type::U128(u64::MAX as u128 + 1),
+            db::Type::U128(u128::MAX),
+            db::Type::Decimal(MAX_DECIMAL_PRECISION, 2, -12345),
+            db::Type::Uuid(u128::MAX - 1),
+        ];
+        for value in values {
+            let proto_value: proto::Value = value.clone().into();
+            assert_eq!(db::Type::try_from(proto_value), Ok(value));
         }
-        .into();
-        db::FilterOption::new(field, filter)
+    }
+
+    #[test]
+    fn filter_expressions() {
+        use proto::filter_expression::Kind;
+        let equal = |column: &str| proto::FilterExpression {
+            kind: Some(Kind::Filter(proto::FilterOption {
+                column: String::from(column),
+                filter: proto::Filter::Equal.into(),
+                value: Some(db::Type::I32(1).into()),
+                ..Default::default()
+            })),
+        };
+        let list = |expressions| proto::FilterExpressions { expressions };
+        let expression = proto::FilterExpression {
+            kind: Some(Kind::Not(Box::new(proto::FilterExpression {
+                kind: Some(Kind::Or(list(vec![
+                    equal("a"),
+                    proto::FilterExpression {
+                        kind: Some(Kind::And(list(vec![]))),
+                    },
+                ]))),
+            }))),
+        };
+        let db::FilterExpression::Not(expression) = expression.try_into().unwrap() else {
+            panic!("'not' expected");
+        };
+        let db::FilterExpression::Or(expressions) = *expression else {
+            panic!("'or' expected");
+        };
+        assert!(matches!(
+            &expressions[..],
+            [db::FilterExpression::Filter(option), db::FilterExpression::And(and)]
+                if option.column() == "a" && *option.value() == db::Type::I32(1) && and.is_empty()
+        ));
+
+        let empty = proto::FilterExpression {
+            kind: Some(Kind::Or(list(vec![proto::FilterExpression { kind: None }]))),
+        };
+        assert!(db::FilterExpression::try_from(empty).is_err());
     }
 }